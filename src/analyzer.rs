@@ -0,0 +1,241 @@
+//! A reusable handle for analyzing one database.
+//!
+//! [`Analyzer`] keeps a single read-only connection open for its whole life and
+//! remembers what it has already worked out, so flipping between views in the TUI
+//! or asking for the same table twice from another crate does not walk the b-trees
+//! again. The `analyze_*` functions in [`crate::app`] are one-shot wrappers around it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use rusqlite::Connection;
+
+use crate::app::{
+    dbstat_available, describe_table, list_indexes, measure, open_database, AnalysisOptions,
+    IndexInfo, RowCountMode, SpaceSummary, TableDetails, TableInfo,
+};
+use crate::error::Result;
+use crate::findings::{
+    redundant_indexes, sort_findings, unindexed_foreign_keys, Finding, RedundantIndex,
+    UnindexedForeignKey,
+};
+use crate::snapshot::Snapshot;
+use crate::worker::{count_rows_parallel, AnalysisEvent};
+use crate::workload::{index_usage, IndexUsage, Workload};
+
+pub struct Analyzer {
+    source_path: String,
+    db_path: String,
+    options: AnalysisOptions,
+    conn: Connection,
+    /// Keeps the copy being analyzed alive when `options.snapshot` was set.
+    snapshot: Option<Snapshot>,
+    measured: Option<(Vec<TableInfo>, SpaceSummary)>,
+    rows_counted: bool,
+    indexes: HashMap<String, Vec<IndexInfo>>,
+    details: HashMap<String, TableDetails>,
+}
+
+impl Analyzer {
+    /// Opens `db_path` read-only, first copying it when `options.snapshot` is set.
+    pub fn open(db_path: &str, mut options: AnalysisOptions) -> Result<Self> {
+        let snapshot = if options.snapshot {
+            let snapshot = Snapshot::create(db_path, options.open)?;
+            // Nothing else writes to the private copy
+            options.snapshot = false;
+            options.open.immutable = true;
+            Some(snapshot)
+        } else {
+            None
+        };
+        let path = snapshot.as_ref().map_or(db_path, |s| s.path());
+        let conn = open_database(path, options.open)?;
+
+        Ok(Self {
+            source_path: db_path.to_string(),
+            db_path: path.to_string(),
+            options,
+            conn,
+            snapshot,
+            measured: None,
+            rows_counted: false,
+            indexes: HashMap::new(),
+            details: HashMap::new(),
+        })
+    }
+
+    /// The file being analyzed; the snapshot copy rather than the original when
+    /// there is one.
+    pub fn db_path(&self) -> &str {
+        &self.db_path
+    }
+
+    /// The database as named by the caller, even when a snapshot of it is analyzed.
+    pub fn source_path(&self) -> &str {
+        &self.source_path
+    }
+
+    /// Options for further connections to [`Self::db_path`], such as those of a
+    /// background analysis.
+    pub fn options(&self) -> AnalysisOptions {
+        self.options
+    }
+
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot.is_some()
+    }
+
+    /// The journal mode of the database as named by the caller, which a
+    /// snapshot copy does not share.
+    pub fn journal_mode(&self) -> Result<String> {
+        match &self.snapshot {
+            Some(snapshot) => Ok(snapshot.journal_mode().to_string()),
+            None => Ok(self
+                .conn
+                .query_row("PRAGMA journal_mode", [], |row| row.get(0))?),
+        }
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn has_dbstat(&self) -> bool {
+        dbstat_available(&self.conn)
+    }
+
+    /// All tables, largest first, with row counts as asked for by the options.
+    pub fn tables(&mut self) -> Result<&[TableInfo]> {
+        self.measure()?;
+        if self.options.row_counts == RowCountMode::Exact && !self.rows_counted {
+            self.count_rows()?;
+        }
+        Ok(&self.measured.as_ref().unwrap().0)
+    }
+
+    /// How the pages of the file divide up; needs no row counts.
+    pub fn space(&mut self) -> Result<&SpaceSummary> {
+        self.measure()?;
+        Ok(&self.measured.as_ref().unwrap().1)
+    }
+
+    /// The indexes of one table, largest first.
+    pub fn indexes(&mut self, table_name: &str) -> Result<&[IndexInfo]> {
+        if !self.indexes.contains_key(table_name) {
+            let indexes = list_indexes(
+                &self.conn,
+                &self.db_path,
+                table_name,
+                self.options.hide_auto_indexes,
+            )?;
+            self.indexes.insert(table_name.to_string(), indexes);
+        }
+        Ok(&self.indexes[table_name])
+    }
+
+    /// Indexes of one table that another index or constraint makes unnecessary.
+    pub fn redundant_indexes(&mut self, table_name: &str) -> Result<Vec<RedundantIndex>> {
+        self.indexes(table_name)?;
+        redundant_indexes(&self.conn, table_name, &self.indexes[table_name])
+    }
+
+    /// Foreign keys of one table that no index serves.
+    pub fn unindexed_foreign_keys(&mut self, table_name: &str) -> Result<Vec<UnindexedForeignKey>> {
+        self.table_details(table_name)?;
+        unindexed_foreign_keys(
+            &self.conn,
+            table_name,
+            &self.details[table_name].foreign_keys,
+        )
+    }
+
+    /// Problems across all tables, the most bytes to reclaim first; needs no
+    /// row counts.
+    pub fn findings(&mut self) -> Result<Vec<Finding>> {
+        self.measure()?;
+        let names: Vec<String> = self
+            .measured
+            .as_ref()
+            .unwrap()
+            .0
+            .iter()
+            .map(|t| t.name.clone())
+            .collect();
+
+        let mut findings = Vec::new();
+        for name in names {
+            findings.extend(self.table_findings(&name)?);
+        }
+        sort_findings(&mut findings);
+        Ok(findings)
+    }
+
+    /// Problems with one table and its indexes.
+    pub fn table_findings(&mut self, table_name: &str) -> Result<Vec<Finding>> {
+        let mut findings: Vec<Finding> = self
+            .redundant_indexes(table_name)?
+            .into_iter()
+            .map(Finding::RedundantIndex)
+            .collect();
+        findings.extend(
+            self.unindexed_foreign_keys(table_name)?
+                .into_iter()
+                .map(Finding::UnindexedForeignKey),
+        );
+        Ok(findings)
+    }
+
+    /// How often the query plans of `workload` use each index.
+    pub fn index_usage(&self, workload: &Workload) -> Result<IndexUsage> {
+        index_usage(&self.conn, workload)
+    }
+
+    /// Schema, columns, foreign keys and triggers of one table.
+    pub fn table_details(&mut self, table_name: &str) -> Result<&TableDetails> {
+        if !self.details.contains_key(table_name) {
+            let details = describe_table(&self.conn, table_name)?;
+            self.details.insert(table_name.to_string(), details);
+        }
+        Ok(&self.details[table_name])
+    }
+
+    /// Forgets everything worked out so far, for a database that has changed.
+    pub fn refresh(&mut self) {
+        self.measured = None;
+        self.rows_counted = false;
+        self.indexes.clear();
+        self.details.clear();
+    }
+
+    fn measure(&mut self) -> Result<()> {
+        if self.measured.is_none() {
+            self.measured = Some(measure(&self.conn, &self.db_path, self.options)?);
+            self.rows_counted = false;
+        }
+        Ok(())
+    }
+
+    fn count_rows(&mut self) -> Result<()> {
+        let tables = &mut self.measured.as_mut().unwrap().0;
+        let names = tables.iter().map(|t| t.name.clone()).collect();
+        let counts = Mutex::new(HashMap::new());
+        count_rows_parallel(&self.db_path, names, self.options, |event| {
+            if let AnalysisEvent::Counted {
+                name,
+                row_count,
+                error,
+            } = event
+            {
+                counts.lock().unwrap().insert(name, (row_count, error));
+            }
+        })?;
+
+        let mut counts = counts.into_inner().unwrap();
+        for table in tables.iter_mut() {
+            let (row_count, error) = counts.remove(&table.name).unwrap_or_default();
+            table.apply_row_count(row_count, error);
+        }
+        self.rows_counted = true;
+        Ok(())
+    }
+}
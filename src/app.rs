@@ -1,26 +1,321 @@
-use anyhow::{Context, Result};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 use ratatui::widgets::ListState;
-use rusqlite::{Connection, Result as SqliteResult};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Result as SqliteResult};
+
+use crate::analyzer::Analyzer;
+use crate::budget::Budget;
+use crate::diff::DatabaseDiff;
+use crate::error::{Result, SqduError};
+use crate::fileformat::{DbFile, HEADER_MAGIC};
+use crate::findings::{Finding, RedundantIndex, UnindexedForeignKey};
+use crate::format::UnitStyle;
+use crate::history::{History, Series};
+use crate::worker::AnalysisEvent;
+use crate::workload::IndexUsage;
+
+/// Page counts and byte totals of a single b-tree, split by `dbstat.pagetype`.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageStats {
+    pub interior_pages: u64,
+    pub interior_bytes: u64,
+    pub leaf_pages: u64,
+    pub leaf_bytes: u64,
+    pub overflow_pages: u64,
+    pub overflow_bytes: u64,
+    /// Bytes of record payload stored on the pages (`dbstat.payload`).
+    pub payload_bytes: u64,
+    /// Bytes allocated to the pages but holding nothing (`dbstat.unused`).
+    pub unused_bytes: u64,
+    /// Cells on leaf pages; for a table b-tree this is its number of rows.
+    pub leaf_cells: u64,
+}
+
+impl PageStats {
+    pub fn total_pages(&self) -> u64 {
+        self.interior_pages + self.leaf_pages + self.overflow_pages
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.interior_bytes + self.leaf_bytes + self.overflow_bytes
+    }
+
+    /// Fraction of allocated bytes that hold payload, from 0.0 to 1.0.
+    pub fn efficiency(&self) -> f64 {
+        let total = self.total_bytes();
+        if total == 0 {
+            0.0
+        } else {
+            self.payload_bytes as f64 / total as f64
+        }
+    }
+
+    pub fn avg_unused_per_page(&self) -> u64 {
+        self.unused_bytes
+            .checked_div(self.total_pages())
+            .unwrap_or(0)
+    }
+}
+
+/// Every page of the database file, attributed to the kind of object that owns it.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpaceSummary {
+    pub page_size: u64,
+    pub page_count: u64,
+    pub table_bytes: u64,
+    /// Pages of all indexes, including the automatic ones behind constraints.
+    pub index_bytes: u64,
+    /// Pages of SQLite's own tables: the schema table, `sqlite_sequence` and
+    /// `sqlite_stat*`.
+    pub internal_bytes: u64,
+    pub freelist_bytes: u64,
+}
+
+impl SpaceSummary {
+    pub fn file_bytes(&self) -> u64 {
+        self.page_count * self.page_size
+    }
+
+    /// Pages not owned by any b-tree or the freelist, such as pointer-map pages in
+    /// auto-vacuum databases and the lock-byte page.
+    pub fn other_bytes(&self) -> u64 {
+        self.file_bytes().saturating_sub(
+            self.table_bytes + self.index_bytes + self.internal_bytes + self.freelist_bytes,
+        )
+    }
+
+    /// Share of the whole file taken by `bytes`, in percent.
+    pub fn percentage(&self, bytes: u64) -> f64 {
+        let total = self.file_bytes();
+        if total == 0 {
+            0.0
+        } else {
+            (bytes as f64 / total as f64) * 100.0
+        }
+    }
+}
+
+/// How far a table's `row_count` can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RowCount {
+    /// Not counted yet and no estimate available; `row_count` is 0.
+    Pending,
+    /// Taken from the number of cells on the leaf pages of the table b-tree.
+    Estimated,
+    /// Counted with `COUNT(*)`.
+    Exact,
+    /// Counting was interrupted or failed and there was no estimate to fall
+    /// back on; `row_count` is 0.
+    Skipped,
+}
+
+/// Whether analysis runs `COUNT(*)` on every table or settles for estimates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RowCountMode {
+    #[default]
+    Exact,
+    Estimate,
+}
+
+/// How database files are opened. Connections are always read-only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenOptions {
+    /// Open with `immutable=1`: SQLite takes no locks and assumes nothing else
+    /// changes the file, which lets it read databases on read-only media.
+    pub immutable: bool,
+    /// How long to wait for a writer's lock before giving up with `SQLITE_BUSY`.
+    pub busy_timeout: Duration,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            immutable: false,
+            busy_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Settings for a full analysis run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnalysisOptions {
+    pub row_counts: RowCountMode,
+    /// Number of connections counting rows at the same time.
+    pub jobs: usize,
+    pub open: OpenOptions,
+    /// Analyze a [`crate::snapshot::Snapshot`] of the database instead of the live file.
+    pub snapshot: bool,
+    /// List SQLite's own tables (`sqlite_schema`, `sqlite_sequence`, `sqlite_stat*`)
+    /// alongside the user's tables.
+    pub include_internal: bool,
+    /// Leave out the indexes SQLite creates for PRIMARY KEY and UNIQUE
+    /// constraints when listing and counting indexes; their size still counts.
+    pub hide_auto_indexes: bool,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        Self {
+            row_counts: RowCountMode::default(),
+            jobs: default_jobs(),
+            open: OpenOptions::default(),
+            snapshot: false,
+            include_internal: false,
+            hide_auto_indexes: false,
+        }
+    }
+}
+
+/// One row-counting connection per available core.
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TableInfo {
     pub name: String,
     pub size_bytes: u64,
     pub row_count: u64,
+    pub row_count_kind: RowCount,
     pub index_count: u64,
     pub index_size_bytes: u64,
+    pub pages: PageStats,
+    /// Why this table, one of its indexes or its row count could not be
+    /// measured; the affected numbers are then incomplete rather than zero.
+    pub error: Option<String>,
+}
+
+impl TableInfo {
+    pub fn set_row_count(&mut self, row_count: u64) {
+        self.row_count = row_count;
+        self.row_count_kind = RowCount::Exact;
+    }
+
+    /// Gives up on an exact count, keeping the estimate if there is one.
+    pub fn skip_row_count(&mut self) {
+        if self.row_count_kind == RowCount::Pending {
+            self.row_count_kind = RowCount::Skipped;
+        }
+    }
+
+    /// The row count, unless it was never counted or estimated.
+    pub fn known_row_count(&self) -> Option<u64> {
+        match self.row_count_kind {
+            RowCount::Exact | RowCount::Estimated => Some(self.row_count),
+            RowCount::Pending | RowCount::Skipped => None,
+        }
+    }
+
+    /// Records the outcome of a `COUNT(*)`: a count, or a skip with an optional error.
+    pub fn apply_row_count(&mut self, row_count: Option<u64>, error: Option<String>) {
+        match row_count {
+            Some(n) => self.set_row_count(n),
+            None => self.skip_row_count(),
+        }
+        if let Some(e) = error {
+            self.error = Some(format!("row count: {}", e));
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IndexInfo {
     pub name: String,
     pub size_bytes: u64,
     pub is_unique: bool,
+    /// The key columns as they would appear in CREATE INDEX, e.g.
+    /// `user_id, created_at DESC`.
     pub columns: String,
+    /// The WHERE clause of a partial index.
     pub partial_clause: Option<String>,
+    pub pages: PageStats,
+    /// Why the pages of this index could not be measured.
+    pub error: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub origin: IndexOrigin,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub is_partial: bool,
+    /// The key columns in order, as `PRAGMA index_xinfo` reports them.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key_columns: Vec<IndexColumn>,
+}
+
+/// What created an index, from the `origin` column of `PRAGMA index_list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum IndexOrigin {
+    /// A CREATE INDEX statement.
+    #[default]
+    CreateIndex,
+    /// A UNIQUE constraint.
+    Unique,
+    /// A PRIMARY KEY constraint.
+    PrimaryKey,
+}
+
+impl IndexOrigin {
+    fn from_pragma(origin: &str) -> Self {
+        match origin {
+            "u" => IndexOrigin::Unique,
+            "pk" => IndexOrigin::PrimaryKey,
+            _ => IndexOrigin::CreateIndex,
+        }
+    }
+
+    /// The constraint an automatic index was created for, e.g. "PRIMARY KEY".
+    pub fn constraint(&self) -> Option<&'static str> {
+        match self {
+            IndexOrigin::CreateIndex => None,
+            IndexOrigin::Unique => Some("UNIQUE"),
+            IndexOrigin::PrimaryKey => Some("PRIMARY KEY"),
+        }
+    }
+}
+
+/// One key column of an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexColumn {
+    /// The table column; `None` for an expression.
+    pub name: Option<String>,
+    /// The SQL of an expression column, without COLLATE and ASC/DESC.
+    pub expression: Option<String>,
+    pub descending: bool,
+    /// Collating sequence, `BINARY` unless the index or the column names another.
+    pub collation: String,
+}
+
+impl IndexColumn {
+    /// The column as it would appear in CREATE INDEX.
+    pub fn to_sql(&self) -> String {
+        let mut sql = match (&self.name, &self.expression) {
+            (Some(name), _) => name.clone(),
+            (None, Some(expression)) => expression.clone(),
+            (None, None) => String::from("<expression>"),
+        };
+        if !self.collation.eq_ignore_ascii_case("BINARY") {
+            sql.push_str(&format!(" COLLATE {}", self.collation));
+        }
+        if self.descending {
+            sql.push_str(" DESC");
+        }
+        sql
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ColumnInfo {
     pub name: String,
     pub col_type: String,
@@ -30,15 +325,24 @@ pub struct ColumnInfo {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ForeignKeyInfo {
+    /// Shared by the columns of one foreign key; missing from older reports.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub id: i64,
+    /// Position of the column within a composite key.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seq: i64,
     pub from_col: String,
     pub to_table: String,
-    pub to_col: String,
+    /// `None` when the key refers to the parent's primary key without naming it.
+    pub to_col: Option<String>,
     pub on_update: String,
     pub on_delete: String,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TableDetails {
     pub ddl: String,
     pub columns: Vec<ColumnInfo>,
@@ -46,26 +350,116 @@ pub struct TableDetails {
     pub triggers: Vec<String>,
 }
 
+/// Order of the table overview and of reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    /// Largest table first.
+    #[default]
+    Size,
+    Name,
+    /// Most rows first.
+    Rows,
+    /// Largest total index size first.
+    IndexSize,
+    /// Emptiest b-tree first, i.e. the best candidates for `VACUUM`.
+    Fill,
+}
+
+impl SortKey {
+    /// Every sort key, by the name used on the command line.
+    pub const ALL: &'static [(&'static str, SortKey)] = &[
+        ("size", SortKey::Size),
+        ("name", SortKey::Name),
+        ("rows", SortKey::Rows),
+        ("index-size", SortKey::IndexSize),
+        ("fill", SortKey::Fill),
+    ];
+
+    pub fn compare(self, a: &TableInfo, b: &TableInfo) -> Ordering {
+        let order = match self {
+            SortKey::Size => b.size_bytes.cmp(&a.size_bytes),
+            SortKey::Name => Ordering::Equal,
+            SortKey::Rows => b.row_count.cmp(&a.row_count),
+            SortKey::IndexSize => b.index_size_bytes.cmp(&a.index_size_bytes),
+            SortKey::Fill => a.pages.efficiency().total_cmp(&b.pages.efficiency()),
+        };
+        // Ties, and everything when sorting by name, go alphabetically
+        order.then_with(|| a.name.cmp(&b.name))
+    }
+}
+
 pub enum ViewMode {
     Tables,
     Indexes(String),   // table name
     TableInfo(String), // table name
+    /// Size changes between two databases, see [`App::diffing`].
+    Diff,
+    /// Problems found across the whole database, see [`App::findings`].
+    Findings,
+}
+
+/// Progress of the background analysis started by [`crate::worker::spawn_analysis`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisState {
+    /// Walking the b-trees; no tables are known yet.
+    Measuring,
+    Counting {
+        counted: usize,
+        total: usize,
+        /// Tables whose `COUNT(*)` is running right now.
+        current: Vec<String>,
+    },
+    Done,
+    Cancelled,
+    Failed(String),
+}
+
+impl AnalysisState {
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self,
+            AnalysisState::Measuring | AnalysisState::Counting { .. }
+        )
+    }
 }
 
 pub struct App {
     pub tables: Vec<TableInfo>,
     pub indexes: Vec<IndexInfo>,
+    /// Those of `indexes` that another index or constraint makes unnecessary.
+    pub redundant: Vec<RedundantIndex>,
+    /// Problems across the whole database, the most bytes to reclaim first.
+    pub findings: Vec<Finding>,
     pub table_details: Option<TableDetails>,
+    /// Foreign keys in `table_details` that no index serves.
+    pub unindexed: Vec<UnindexedForeignKey>,
     pub list_state: ListState,
     pub scroll_offset: u16,
     pub db_path: String,
     pub total_size: u64,
+    pub space: SpaceSummary,
     pub view_mode: ViewMode,
+    /// Problems with the analysis itself, shown in a banner above every view.
+    pub warnings: Vec<String>,
+    pub analysis: AnalysisState,
+    pub sort: SortKey,
+    pub units: UnitStyle,
+    pub diff: Option<DatabaseDiff>,
+    /// Earlier analyses of this database; empty when none were recorded.
+    pub history: History,
+    /// Size to project table growth to; twice the current size when not set.
+    pub target_size: Option<u64>,
+    /// Seconds since the Unix epoch when this analysis started.
+    pub analyzed_at: u64,
+    /// Limits to highlight tables over; `None` when no budget was given.
+    pub budget: Option<Budget>,
+    /// Index hits of a query workload; `None` when no workload was given.
+    pub usage: Option<IndexUsage>,
 }
 
 impl App {
-    pub fn new(db_path: String, tables: Vec<TableInfo>) -> Self {
-        let total_size = tables.iter().map(|t| t.size_bytes).sum();
+    pub fn new(db_path: String, tables: Vec<TableInfo>, space: SpaceSummary) -> Self {
+        let total_size = space.file_bytes();
         let mut list_state = ListState::default();
         if !tables.is_empty() {
             // Start at index 2 to skip header rows
@@ -74,12 +468,133 @@ impl App {
         Self {
             tables,
             indexes: Vec::new(),
+            redundant: Vec::new(),
+            findings: Vec::new(),
             table_details: None,
+            unindexed: Vec::new(),
             list_state,
             scroll_offset: 0,
             db_path,
             total_size,
+            space,
             view_mode: ViewMode::Tables,
+            warnings: Vec::new(),
+            analysis: AnalysisState::Done,
+            sort: SortKey::default(),
+            units: UnitStyle::default(),
+            diff: None,
+            history: History::default(),
+            target_size: None,
+            analyzed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            budget: None,
+            usage: None,
+        }
+    }
+
+    /// An app with nothing to show yet, waiting for events from a background analysis.
+    pub fn analyzing(db_path: String) -> Self {
+        let mut app = Self::new(db_path, Vec::new(), SpaceSummary::default());
+        app.analysis = AnalysisState::Measuring;
+        app
+    }
+
+    /// An app showing the changes from one database to another.
+    pub fn diffing(diff: DatabaseDiff) -> Self {
+        let mut app = Self::new(diff.new.path.clone(), Vec::new(), SpaceSummary::default());
+        app.total_size = diff.file.new.unwrap_or(0);
+        app.view_mode = ViewMode::Diff;
+        app.list_state.select(if diff.object_count() > 0 {
+            // Start at index 2 to skip header rows
+            Some(2)
+        } else {
+            None
+        });
+        app.diff = Some(diff);
+        app
+    }
+
+    /// Marks exact row counting for `total` tables as under way.
+    pub fn start_counting(&mut self, total: usize) {
+        self.analysis = AnalysisState::Counting {
+            counted: 0,
+            total,
+            current: Vec::new(),
+        };
+    }
+
+    /// Folds one event from the background analysis into the app state.
+    pub fn apply(&mut self, event: AnalysisEvent) {
+        match event {
+            AnalysisEvent::Measured { tables, space } => {
+                self.tables = tables;
+                self.sort_tables();
+                self.total_size = space.file_bytes();
+                self.space = space;
+                if let ViewMode::Tables = self.view_mode {
+                    if !self.tables.is_empty() {
+                        // Start at index 2 to skip header rows
+                        self.list_state.select(Some(2));
+                    }
+                }
+            }
+            AnalysisEvent::CountingRows(total) => self.start_counting(total),
+            AnalysisEvent::Counting(name) => {
+                if let AnalysisState::Counting { current, .. } = &mut self.analysis {
+                    current.push(name);
+                }
+            }
+            AnalysisEvent::Counted {
+                name,
+                row_count,
+                error,
+            } => {
+                if let Some(table) = self.tables.iter_mut().find(|t| t.name == name) {
+                    table.apply_row_count(row_count, error);
+                }
+                if self.sort == SortKey::Rows {
+                    self.sort_tables();
+                }
+                if let AnalysisState::Counting {
+                    counted, current, ..
+                } = &mut self.analysis
+                {
+                    *counted += 1;
+                    current.retain(|n| *n != name);
+                }
+            }
+            AnalysisEvent::Finished => self.analysis = AnalysisState::Done,
+            AnalysisEvent::Cancelled => {
+                for table in &mut self.tables {
+                    table.skip_row_count();
+                }
+                self.analysis = AnalysisState::Cancelled;
+            }
+            AnalysisEvent::Failed(message) => {
+                self.warnings.push(format!("Analysis failed: {}", message));
+                self.analysis = AnalysisState::Failed(message);
+            }
+        }
+    }
+
+    /// Recorded sizes of `table` followed by its size in this analysis.
+    pub fn table_series(&self, table: &TableInfo) -> Series {
+        self.history
+            .series(&table.name)
+            .with_point(self.analyzed_at, table.size_bytes)
+    }
+
+    /// Orders the tables by `self.sort`, keeping the selected table selected.
+    pub fn sort_tables(&mut self) {
+        let selected = self.selected_table().map(|t| t.name.clone());
+        let sort = self.sort;
+        self.tables.sort_by(|a, b| sort.compare(a, b));
+        if let (ViewMode::Tables, Some(name)) = (&self.view_mode, selected) {
+            if let Some(i) = self.tables.iter().position(|t| t.name == name) {
+                // Offset by 2 for header rows
+                self.list_state.select(Some(i + 2));
+            }
         }
     }
 
@@ -88,6 +603,8 @@ impl App {
             ViewMode::Tables => self.tables.len(),
             ViewMode::Indexes(_) => self.indexes.len(),
             ViewMode::TableInfo(_) => 0, // No navigation in info view
+            ViewMode::Diff => self.diff.as_ref().map_or(0, |d| d.object_count()),
+            ViewMode::Findings => self.findings.len(),
         };
 
         if len == 0 {
@@ -113,6 +630,8 @@ impl App {
             ViewMode::Tables => self.tables.len(),
             ViewMode::Indexes(_) => self.indexes.len(),
             ViewMode::TableInfo(_) => 0, // No navigation in info view
+            ViewMode::Diff => self.diff.as_ref().map_or(0, |d| d.object_count()),
+            ViewMode::Findings => self.findings.len(),
         };
 
         if len == 0 {
@@ -133,6 +652,13 @@ impl App {
         self.list_state.select(Some(i));
     }
 
+    /// The table under the cursor in the tables view.
+    pub fn selected_table(&self) -> Option<&TableInfo> {
+        // Subtract 2 to account for header rows
+        let i = self.list_state.selected()?;
+        self.tables.get(i.checked_sub(2)?)
+    }
+
     pub fn scroll_down(&mut self) {
         self.scroll_offset = self.scroll_offset.saturating_add(1);
     }
@@ -146,150 +672,582 @@ impl App {
     }
 }
 
-pub fn analyze_database(db_path: &str) -> Result<Vec<TableInfo>> {
-    let conn = Connection::open(db_path).context("Failed to open database")?;
+/// Where per-object page statistics come from.
+enum PageSource {
+    /// SQLite's `dbstat` virtual table.
+    Dbstat,
+    /// sqdu's own b-tree walker, for SQLite builds without `dbstat`.
+    File(DbFile),
+}
+
+impl PageSource {
+    fn detect(conn: &Connection, db_path: &str) -> Result<Self> {
+        if dbstat_available(conn) {
+            Ok(PageSource::Dbstat)
+        } else {
+            let file = DbFile::open(db_path).map_err(SqduError::DbstatMissing)?;
+            Ok(PageSource::File(file))
+        }
+    }
 
-    let mut tables = Vec::new();
+    /// Page statistics of a single object.
+    fn stats(&mut self, conn: &Connection, name: &str) -> Result<PageStats> {
+        let stats = match self {
+            PageSource::Dbstat => dbstat_page_stats(conn, name)?,
+            // Views and virtual tables have no b-tree of their own
+            PageSource::File(file) => match root_page(conn, name)? {
+                Some(root) => file.btree_stats(root)?,
+                None => PageStats::default(),
+            },
+        };
+        Ok(stats)
+    }
+
+    /// Page statistics of every b-tree in the file, keyed by object name, gathered
+    /// in a single pass over `dbstat` (or over the file). The file reader measures
+    /// each b-tree on its own, so a damaged one ends up in the second map instead
+    /// of failing the others.
+    fn collect(
+        &mut self,
+        conn: &Connection,
+        objects: &[SchemaObject],
+    ) -> Result<(HashMap<String, PageStats>, HashMap<String, String>)> {
+        match self {
+            PageSource::Dbstat => Ok((dbstat_all_page_stats(conn)?, HashMap::new())),
+            PageSource::File(file) => {
+                let mut all = HashMap::new();
+                let mut errors = HashMap::new();
+                all.insert("sqlite_schema".to_string(), file.btree_stats(1)?);
+                for object in objects.iter().filter(|o| o.rootpage > 0) {
+                    match file.btree_stats(object.rootpage) {
+                        Ok(stats) => {
+                            all.insert(object.name.clone(), stats);
+                        }
+                        Err(e) => {
+                            errors.insert(object.name.clone(), SqduError::from(e).to_string());
+                        }
+                    }
+                }
+                Ok((all, errors))
+            }
+        }
+    }
+}
 
-    // Get all table names
+/// One row of `sqlite_master`.
+struct SchemaObject {
+    name: String,
+    object_type: String,
+    tbl_name: String,
+    rootpage: u32,
+    /// A table matching `sqlite_%`, i.e. one that belongs to SQLite itself. The
+    /// automatic `sqlite_autoindex_*` indexes belong to the user's constraints.
+    internal: bool,
+}
+
+fn schema_objects(conn: &Connection) -> SqliteResult<Vec<SchemaObject>> {
     let mut stmt = conn.prepare(
-        "SELECT name FROM sqlite_master WHERE type='table' AND name NOT LIKE 'sqlite_%' ORDER BY name"
+        "SELECT name, type, tbl_name, COALESCE(rootpage, 0), name LIKE 'sqlite_%' AND type != 'index'
+         FROM sqlite_master ORDER BY name",
     )?;
+    let objects = stmt
+        .query_map([], |row| {
+            Ok(SchemaObject {
+                name: row.get(0)?,
+                object_type: row.get(1)?,
+                tbl_name: row.get(2)?,
+                rootpage: row.get(3)?,
+                internal: row.get(4)?,
+            })
+        })?
+        .collect::<SqliteResult<Vec<SchemaObject>>>()?;
+    Ok(objects)
+}
 
-    let table_names: Vec<String> = stmt
-        .query_map([], |row| row.get(0))?
-        .collect::<SqliteResult<Vec<String>>>()?;
+/// Whether the linked SQLite was compiled with `SQLITE_ENABLE_DBSTAT_VTAB`.
+pub fn dbstat_available(conn: &Connection) -> bool {
+    conn.prepare("SELECT 1 FROM dbstat LIMIT 1").is_ok()
+}
 
-    for table_name in table_names {
-        // Get row count
-        let row_count: u64 = conn
-            .query_row(
-                &format!("SELECT COUNT(*) FROM \"{}\"", table_name),
-                [],
-                |row| row.get(0),
-            )
-            .unwrap_or(0);
-
-        // Get approximate size (number of pages * page size)
-        let size_bytes: u64 = conn
-            .query_row(
-                "SELECT SUM(pgsize) FROM dbstat WHERE name = ?1",
-                [&table_name],
-                |row| row.get(0),
-            )
-            .unwrap_or(0);
-
-        // Count indexes for this table
-        let index_count: u64 = conn
-            .query_row(
-                "SELECT COUNT(*) FROM sqlite_master WHERE type='index' AND tbl_name=?1 AND name NOT LIKE 'sqlite_%'",
-                [&table_name],
-                |row| row.get(0),
-            )
-            .unwrap_or(0);
-
-        // Get total index size for this table
-        let index_size_bytes: u64 = conn
-            .query_row(
-                "SELECT COALESCE(SUM(pgsize), 0) FROM dbstat WHERE name IN (SELECT name FROM sqlite_master WHERE type='index' AND tbl_name=?1)",
-                [&table_name],
-                |row| row.get(0),
-            )
-            .unwrap_or(0);
-
-        tables.push(TableInfo {
-            name: table_name,
-            size_bytes,
-            row_count,
-            index_count,
-            index_size_bytes,
-        });
+/// Opens `db_path` only to check for `dbstat`, so callers can warn before analysis.
+pub fn probe_dbstat(db_path: &str) -> Result<bool> {
+    let conn = open_database(db_path, OpenOptions::default())?;
+    Ok(dbstat_available(&conn))
+}
+
+/// Opens an existing database without ever creating or writing to it.
+///
+/// Unlike `Connection::open`, a missing file or one that does not start with the
+/// SQLite header is an error rather than a new empty database. The connection is
+/// opened read-only and additionally set to `query_only`.
+pub fn open_database(db_path: &str, options: OpenOptions) -> Result<Connection> {
+    let mut file = File::open(db_path).map_err(|e| SqduError::open(db_path, e))?;
+    let mut magic = [0u8; 16];
+    if file.read_exact(&mut magic).is_err() || &magic != HEADER_MAGIC {
+        return Err(SqduError::NotADatabase(db_path.to_string()));
     }
+    drop(file);
 
-    // Sort by size descending
-    tables.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+    let mut uri = format!("file:{}?mode=ro", uri_path(db_path));
+    if options.immutable {
+        uri.push_str("&immutable=1");
+    }
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_URI
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let conn = Connection::open_with_flags(&uri, flags)?;
+    conn.busy_timeout(options.busy_timeout)?;
+    conn.pragma_update(None, "query_only", true)?;
+    Ok(conn)
+}
 
-    Ok(tables)
+/// Escapes the characters that would otherwise end the path part of a URI filename.
+fn uri_path(path: &str) -> String {
+    path.replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23")
 }
 
-pub fn analyze_indexes(db_path: &str, table_name: &str) -> Result<Vec<IndexInfo>> {
-    let conn = Connection::open(db_path).context("Failed to open database")?;
+fn root_page(conn: &Connection, name: &str) -> SqliteResult<Option<u32>> {
+    if name == "sqlite_schema" || name == "sqlite_master" {
+        return Ok(Some(1));
+    }
+    conn.query_row(
+        "SELECT rootpage FROM sqlite_master WHERE name = ?1 AND rootpage > 0",
+        [name],
+        |row| row.get(0),
+    )
+    .optional()
+}
 
-    let mut indexes = Vec::new();
+/// Adds one `dbstat` aggregate row (pages of a single type) to `stats`.
+fn add_dbstat_row(
+    stats: &mut PageStats,
+    pagetype: &str,
+    pages: u64,
+    bytes: u64,
+    payload: u64,
+    unused: u64,
+    cells: u64,
+) {
+    stats.payload_bytes += payload;
+    stats.unused_bytes += unused;
+
+    match pagetype {
+        "internal" => {
+            stats.interior_pages += pages;
+            stats.interior_bytes += bytes;
+        }
+        "leaf" => {
+            stats.leaf_pages += pages;
+            stats.leaf_bytes += bytes;
+            stats.leaf_cells += cells;
+        }
+        "overflow" => {
+            stats.overflow_pages += pages;
+            stats.overflow_bytes += bytes;
+        }
+        _ => {}
+    }
+}
+
+/// Sums the `dbstat` pages of one b-tree, grouped by page type.
+fn dbstat_page_stats(conn: &Connection, name: &str) -> SqliteResult<PageStats> {
+    let mut stats = PageStats::default();
 
-    // Get all indexes for this table
     let mut stmt = conn.prepare(
-        "SELECT name, sql FROM sqlite_master WHERE type='index' AND tbl_name=?1 AND name NOT LIKE 'sqlite_%'"
+        "SELECT pagetype, COUNT(*), COALESCE(SUM(pgsize), 0), COALESCE(SUM(payload), 0), COALESCE(SUM(unused), 0),
+                COALESCE(SUM(ncell), 0)
+         FROM dbstat WHERE name = ?1 GROUP BY pagetype",
     )?;
+    let mut rows = stmt.query([name])?;
+    while let Some(row) = rows.next()? {
+        let pagetype: String = row.get(0)?;
+        add_dbstat_row(
+            &mut stats,
+            &pagetype,
+            row.get(1)?,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+        );
+    }
 
-    let index_data: Vec<(String, Option<String>)> = stmt
-        .query_map([table_name], |row| Ok((row.get(0)?, row.get(1)?)))?
-        .collect::<SqliteResult<Vec<(String, Option<String>)>>>()?;
-
-    for (index_name, sql) in index_data {
-        // Get index size
-        let size_bytes: u64 = conn
-            .query_row(
-                "SELECT COALESCE(SUM(pgsize), 0) FROM dbstat WHERE name = ?1",
-                [&index_name],
-                |row| row.get(0),
-            )
-            .unwrap_or(0);
-
-        // Parse columns, uniqueness, and partial clause from SQL
-        let (is_unique, columns, partial_clause) = if let Some(sql_str) = sql {
-            let is_unique = sql_str.to_uppercase().contains("UNIQUE");
-
-            // Extract column names from CREATE INDEX ... ON table(col1, col2)
-            let columns = if let Some(start) = sql_str.find('(') {
-                // Find WHERE clause if it exists
-                let end = if let Some(where_pos) = sql_str.to_uppercase().find(" WHERE ") {
-                    where_pos.min(sql_str.len())
-                } else {
-                    sql_str.len()
-                };
+    Ok(stats)
+}
 
-                // Find the closing paren before WHERE or end of string
-                if let Some(close_paren) = sql_str[start..end].rfind(')') {
-                    sql_str[start + 1..start + close_paren].to_string()
-                } else {
-                    String::new()
-                }
-            } else {
-                String::new()
-            };
+/// Sums the `dbstat` pages of every b-tree in one scan. The aggregate form
+/// `dbstat('main', 1)` would be cheaper still, but it loses the page types.
+fn dbstat_all_page_stats(conn: &Connection) -> SqliteResult<HashMap<String, PageStats>> {
+    let mut all: HashMap<String, PageStats> = HashMap::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT name, pagetype, COUNT(*), COALESCE(SUM(pgsize), 0), COALESCE(SUM(payload), 0), COALESCE(SUM(unused), 0),
+                COALESCE(SUM(ncell), 0)
+         FROM dbstat GROUP BY name, pagetype",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        let pagetype: String = row.get(1)?;
+        add_dbstat_row(
+            all.entry(name).or_default(),
+            &pagetype,
+            row.get(2)?,
+            row.get(3)?,
+            row.get(4)?,
+            row.get(5)?,
+            row.get(6)?,
+        );
+    }
+
+    Ok(all)
+}
 
-            // Extract WHERE clause for partial indexes
-            let partial_clause = if let Some(where_start) = sql_str.to_uppercase().find(" WHERE ") {
-                Some(sql_str[where_start + 7..].trim().to_string())
+/// Measures every table, largest first, and the file as a whole with a single
+/// pass over the b-trees. Row counts are only estimated, since exact counts need
+/// a full scan of each table; see [`count_rows`]. With `include_internal`
+/// SQLite's own tables are listed too, including the schema table itself, and
+/// with `hide_auto_indexes` automatic indexes are left out of `index_count`.
+pub fn measure(
+    conn: &Connection,
+    db_path: &str,
+    options: AnalysisOptions,
+) -> Result<(Vec<TableInfo>, SpaceSummary)> {
+    let include_internal = options.include_internal;
+    let mut source = PageSource::detect(conn, db_path)?;
+
+    // Read the schema once and measure every b-tree in a single pass
+    let objects = schema_objects(conn)?;
+    let (pages, errors) = source.collect(conn, &objects)?;
+
+    // The schema table has no sqlite_master row of its own
+    let schema = SchemaObject {
+        name: "sqlite_schema".to_string(),
+        object_type: "table".to_string(),
+        tbl_name: "sqlite_schema".to_string(),
+        rootpage: 1,
+        internal: true,
+    };
+    let mut tables: Vec<TableInfo> = objects
+        .iter()
+        .chain(include_internal.then_some(&schema))
+        .filter(|o| o.object_type == "table" && (include_internal || !o.internal))
+        .map(|o| {
+            // Leaf cells of a table b-tree are its rows as of the page scan, which
+            // beats the possibly stale numbers `ANALYZE` leaves in sqlite_stat1
+            let pages = pages.get(&o.name).cloned().unwrap_or_default();
+            let (row_count, row_count_kind) = if pages.total_pages() > 0 {
+                (pages.leaf_cells, RowCount::Estimated)
             } else {
-                None
+                (0, RowCount::Pending)
             };
+            TableInfo {
+                name: o.name.clone(),
+                size_bytes: pages.total_bytes(),
+                row_count,
+                row_count_kind,
+                index_count: 0,
+                index_size_bytes: 0,
+                pages,
+                error: errors.get(&o.name).cloned(),
+            }
+        })
+        .collect();
 
-            (is_unique, columns, partial_clause)
-        } else {
-            // Automatic index (e.g., from PRIMARY KEY)
-            (false, String::from("(auto)"), None)
+    // Attribute indexes to their tables; hidden automatic indexes count towards
+    // the size but not the number of indexes
+    let auto_indexes = if options.hide_auto_indexes {
+        auto_indexes(conn)?
+    } else {
+        HashSet::new()
+    };
+    let positions: HashMap<String, usize> = tables
+        .iter()
+        .enumerate()
+        .map(|(i, t)| (t.name.clone(), i))
+        .collect();
+    for index in objects.iter().filter(|o| o.object_type == "index") {
+        if let Some(&i) = positions.get(&index.tbl_name) {
+            let table = &mut tables[i];
+            table.index_size_bytes += pages.get(&index.name).map_or(0, |p| p.total_bytes());
+            if !auto_indexes.contains(&index.name) {
+                table.index_count += 1;
+            }
+            if let (None, Some(e)) = (&table.error, errors.get(&index.name)) {
+                table.error = Some(format!("index {}: {}", index.name, e));
+            }
+        }
+    }
+
+    // Sort by size descending
+    tables.sort_by_key(|t| std::cmp::Reverse(t.size_bytes));
+
+    let space = space_summary(conn, &objects, &pages)?;
+
+    Ok((tables, space))
+}
+
+/// Indexes SQLite created for a PRIMARY KEY or UNIQUE constraint, by the same
+/// `origin` test [`list_indexes`] hides them by.
+fn auto_indexes(conn: &Connection) -> SqliteResult<HashSet<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT l.name FROM sqlite_master AS m, pragma_index_list(m.name) AS l
+         WHERE m.type = 'table' AND l.origin != 'c'",
+    )?;
+    let names = stmt.query_map([], |row| row.get(0))?.collect();
+    names
+}
+
+/// Counts the rows of one table with a full `COUNT(*)` scan.
+pub fn count_rows(conn: &Connection, table_name: &str) -> SqliteResult<u64> {
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM \"{}\"",
+            table_name.replace('"', "\"\"")
+        ),
+        [],
+        |row| row.get(0),
+    )
+}
+
+pub fn analyze_database(db_path: &str) -> Result<Vec<TableInfo>> {
+    analyze_database_with(db_path, AnalysisOptions::default())
+}
+
+pub fn analyze_database_with(db_path: &str, options: AnalysisOptions) -> Result<Vec<TableInfo>> {
+    Ok(Analyzer::open(db_path, options)?.tables()?.to_vec())
+}
+
+pub fn analyze_space(db_path: &str) -> Result<SpaceSummary> {
+    Ok(Analyzer::open(db_path, AnalysisOptions::default())?
+        .space()?
+        .clone())
+}
+
+fn space_summary(
+    conn: &Connection,
+    objects: &[SchemaObject],
+    pages: &HashMap<String, PageStats>,
+) -> Result<SpaceSummary> {
+    let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+    let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let freelist_count: u64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+
+    let mut summary = SpaceSummary {
+        page_size,
+        page_count,
+        freelist_bytes: freelist_count * page_size,
+        ..SpaceSummary::default()
+    };
+
+    // Attribute every b-tree page to tables, indexes or SQLite's own objects
+    let kinds: HashMap<&str, &SchemaObject> =
+        objects.iter().map(|o| (o.name.as_str(), o)).collect();
+
+    for (name, stats) in pages {
+        let bytes = stats.total_bytes();
+        match kinds.get(name.as_str()) {
+            // Automatic indexes belong to the user's constraints
+            Some(o) if o.object_type == "index" => summary.index_bytes += bytes,
+            Some(o) if !o.internal => summary.table_bytes += bytes,
+            // The schema table itself has no sqlite_master row
+            _ => summary.internal_bytes += bytes,
+        }
+    }
+
+    Ok(summary)
+}
+
+pub fn analyze_indexes(db_path: &str, table_name: &str) -> Result<Vec<IndexInfo>> {
+    Ok(Analyzer::open(db_path, AnalysisOptions::default())?
+        .indexes(table_name)?
+        .to_vec())
+}
+
+/// The indexes of one table on an already open connection, largest first,
+/// including those SQLite created for constraints unless `hide_auto_indexes`.
+pub fn list_indexes(
+    conn: &Connection,
+    db_path: &str,
+    table_name: &str,
+    hide_auto_indexes: bool,
+) -> Result<Vec<IndexInfo>> {
+    let mut source = PageSource::detect(conn, db_path)?;
+
+    let mut indexes = Vec::new();
+
+    // Get all indexes for this table
+    let mut stmt = conn.prepare(
+        "SELECT l.name, l.\"unique\", l.origin, l.partial, m.sql
+         FROM pragma_index_list(?1) AS l LEFT JOIN sqlite_master AS m ON m.name = l.name
+         WHERE NOT ?2 OR l.origin = 'c'",
+    )?;
+
+    let index_data: Vec<(String, bool, String, bool, Option<String>)> = stmt
+        .query_map(params![table_name, hide_auto_indexes], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?
+        .collect::<SqliteResult<_>>()?;
+
+    for (index_name, is_unique, origin, is_partial, sql) in index_data {
+        // Get index size broken down by page type; a damaged index is listed
+        // with its error rather than hiding the others
+        let (pages, error) = match source.stats(conn, &index_name) {
+            Ok(pages) => (pages, None),
+            Err(e) => (PageStats::default(), Some(e.to_string())),
         };
+        let size_bytes = pages.total_bytes();
+
+        // Only the text of expressions and of the WHERE clause needs the SQL;
+        // automatic indexes have none
+        let (terms, where_clause) = sql.as_deref().map(index_sql_parts).unwrap_or_default();
+        let key_columns = index_columns(conn, &index_name, &terms)?;
+        let columns = key_columns
+            .iter()
+            .map(IndexColumn::to_sql)
+            .collect::<Vec<_>>()
+            .join(", ");
 
         indexes.push(IndexInfo {
             name: index_name,
             size_bytes,
             is_unique,
             columns,
-            partial_clause,
+            partial_clause: if is_partial { where_clause } else { None },
+            pages,
+            error,
+            origin: IndexOrigin::from_pragma(&origin),
+            is_partial,
+            key_columns,
         });
     }
 
     // Sort by size descending
-    indexes.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes));
+    indexes.sort_by_key(|i| std::cmp::Reverse(i.size_bytes));
 
     Ok(indexes)
 }
 
+/// The key columns of an index, taking the text of expression columns from
+/// `terms`, the column list of its CREATE INDEX statement.
+fn index_columns(
+    conn: &Connection,
+    index_name: &str,
+    terms: &[String],
+) -> SqliteResult<Vec<IndexColumn>> {
+    let mut stmt = conn.prepare(
+        "SELECT seqno, cid, name, \"desc\", coll FROM pragma_index_xinfo(?1) WHERE key ORDER BY seqno",
+    )?;
+    let columns = stmt
+        .query_map([index_name], |row| {
+            let seqno: usize = row.get(0)?;
+            let cid: i64 = row.get(1)?;
+            Ok(IndexColumn {
+                name: row.get(2)?,
+                // -2 marks an expression
+                expression: (cid == -2)
+                    .then(|| {
+                        terms
+                            .get(seqno)
+                            .map(|t| strip_term_modifiers(t).to_string())
+                    })
+                    .flatten(),
+                descending: row.get(3)?,
+                collation: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            })
+        })?
+        .collect();
+    columns
+}
+
+/// The terms of the column list of a CREATE INDEX statement and the text of its
+/// WHERE clause, skipping over quoted names, strings and nested parentheses.
+fn index_sql_parts(sql: &str) -> (Vec<String>, Option<String>) {
+    let mut terms = Vec::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+    let mut end = None;
+
+    for (i, c) in sql.char_indices() {
+        if let Some(close) = quote {
+            // A doubled quote closes and reopens, which comes out the same
+            if c == close {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '[' => quote = Some(']'),
+            '(' => {
+                depth += 1;
+                if depth == 1 {
+                    start = i + 1;
+                }
+            }
+            ',' if depth == 1 => {
+                terms.push(sql[start..i].trim().to_string());
+                start = i + 1;
+            }
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    terms.push(sql[start..i].trim().to_string());
+                    end = Some(i + 1);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let where_clause = end.and_then(|end| {
+        let rest = sql[end..].trim_start();
+        let keyword = rest.get(..5)?;
+        let clause = &rest[5..];
+        (keyword.eq_ignore_ascii_case("WHERE")
+            && clause.starts_with(|c: char| c.is_whitespace() || c == '('))
+        .then(|| clause.trim().to_string())
+    });
+    (terms, where_clause)
+}
+
+/// An index term without the COLLATE and ASC/DESC that `PRAGMA index_xinfo`
+/// already reports.
+fn strip_term_modifiers(term: &str) -> &str {
+    let mut term = term.trim();
+    if let Some((rest, last)) = term.rsplit_once(char::is_whitespace) {
+        if last.eq_ignore_ascii_case("ASC") || last.eq_ignore_ascii_case("DESC") {
+            term = rest.trim_end();
+        }
+    }
+    if let Some((rest, _)) = term.rsplit_once(char::is_whitespace) {
+        if let Some((expression, keyword)) = rest.trim_end().rsplit_once(char::is_whitespace) {
+            if keyword.eq_ignore_ascii_case("COLLATE") {
+                term = expression.trim_end();
+            }
+        }
+    }
+    term
+}
+
 pub fn analyze_table_details(db_path: &str, table_name: &str) -> Result<TableDetails> {
-    let conn = Connection::open(db_path).context("Failed to open database")?;
+    Ok(Analyzer::open(db_path, AnalysisOptions::default())?
+        .table_details(table_name)?
+        .clone())
+}
 
+/// Schema details of one table on an already open connection.
+pub fn describe_table(conn: &Connection, table_name: &str) -> Result<TableDetails> {
     // Get DDL
     let ddl: String = conn
         .query_row(
@@ -300,24 +1258,30 @@ pub fn analyze_table_details(db_path: &str, table_name: &str) -> Result<TableDet
         .unwrap_or_else(|_| String::from("-- DDL not available"));
 
     // Get column info
-    let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table_name))?;
+    let mut stmt =
+        conn.prepare("SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?1)")?;
     let columns: Vec<ColumnInfo> = stmt
-        .query_map([], |row| {
+        .query_map([table_name], |row| {
             Ok(ColumnInfo {
-                name: row.get(1)?,
-                col_type: row.get(2)?,
-                not_null: row.get::<_, i32>(3)? != 0,
-                default_value: row.get(4)?,
-                is_pk: row.get::<_, i32>(5)? != 0,
+                name: row.get(0)?,
+                col_type: row.get(1)?,
+                not_null: row.get::<_, i32>(2)? != 0,
+                default_value: row.get(3)?,
+                is_pk: row.get::<_, i32>(4)? != 0,
             })
         })?
         .collect::<SqliteResult<Vec<ColumnInfo>>>()?;
 
     // Get foreign keys
-    let mut stmt = conn.prepare(&format!("PRAGMA foreign_key_list(\"{}\")", table_name))?;
+    let mut stmt = conn.prepare(
+        "SELECT id, seq, \"table\", \"from\", \"to\", on_update, on_delete
+         FROM pragma_foreign_key_list(?1) ORDER BY id, seq",
+    )?;
     let foreign_keys: Vec<ForeignKeyInfo> = stmt
-        .query_map([], |row| {
+        .query_map([table_name], |row| {
             Ok(ForeignKeyInfo {
+                id: row.get(0)?,
+                seq: row.get(1)?,
                 from_col: row.get(3)?,
                 to_table: row.get(2)?,
                 to_col: row.get(4)?,
@@ -0,0 +1,291 @@
+//! Size budgets: limits on the file and on tables matched by glob patterns.
+//!
+//! A budget file is TOML. Sizes are byte counts or strings such as `"500 MB"`,
+//! and every pattern that matches a table applies to it:
+//!
+//! ```toml
+//! max_file_size = "2 GB"
+//!
+//! [tables."events_*"]
+//! max_size = "500 MB"
+//! max_rows = 10_000_000
+//! max_index_ratio = 1.5
+//! ```
+
+use crate::app::TableInfo;
+use crate::format::{format_number, UnitStyle};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Budget {
+    pub max_file_size: Option<u64>,
+    /// Sorted by pattern.
+    pub tables: Vec<TableBudget>,
+}
+
+/// Limits for every table whose name matches `pattern`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableBudget {
+    /// `*` matches any run of characters and `?` any single character.
+    pub pattern: String,
+    pub max_size: Option<u64>,
+    pub max_rows: Option<u64>,
+    /// Largest allowed size of the table's indexes relative to the table itself.
+    pub max_index_ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "limit", rename_all = "snake_case"))]
+pub enum Violation {
+    FileSize {
+        actual: u64,
+        max: u64,
+    },
+    TableSize {
+        table: String,
+        pattern: String,
+        actual: u64,
+        max: u64,
+    },
+    Rows {
+        table: String,
+        pattern: String,
+        actual: u64,
+        max: u64,
+    },
+    IndexRatio {
+        table: String,
+        pattern: String,
+        actual: f64,
+        max: f64,
+    },
+}
+
+impl Violation {
+    /// The table over budget, or `None` for the file as a whole.
+    pub fn table(&self) -> Option<&str> {
+        match self {
+            Violation::FileSize { .. } => None,
+            Violation::TableSize { table, .. }
+            | Violation::Rows { table, .. }
+            | Violation::IndexRatio { table, .. } => Some(table),
+        }
+    }
+
+    /// What is over budget and by how much, without the table name.
+    pub fn describe(&self, units: UnitStyle) -> String {
+        match self {
+            Violation::FileSize { actual, max } => format!(
+                "file size {} exceeds {}",
+                units.format(*actual),
+                units.format(*max)
+            ),
+            Violation::TableSize {
+                pattern,
+                actual,
+                max,
+                ..
+            } => format!(
+                "size {} exceeds {} ({})",
+                units.format(*actual),
+                units.format(*max),
+                pattern
+            ),
+            Violation::Rows {
+                pattern,
+                actual,
+                max,
+                ..
+            } => format!(
+                "{} rows exceed {} ({})",
+                format_number(*actual),
+                format_number(*max),
+                pattern
+            ),
+            Violation::IndexRatio {
+                pattern,
+                actual,
+                max,
+                ..
+            } => format!(
+                "index size is {:.2}x the table, more than {:.2}x ({})",
+                actual, max, pattern
+            ),
+        }
+    }
+}
+
+impl Budget {
+    /// Every limit that `tables` or a file of `file_size` bytes exceed.
+    pub fn check(&self, tables: &[TableInfo], file_size: u64) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if let Some(max) = self.max_file_size.filter(|&max| file_size > max) {
+            violations.push(Violation::FileSize {
+                actual: file_size,
+                max,
+            });
+        }
+        for table in tables {
+            violations.extend(self.check_table(table));
+        }
+        violations
+    }
+
+    /// The limits `table` exceeds. Row limits only apply once rows are counted
+    /// or estimated.
+    pub fn check_table(&self, table: &TableInfo) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        for budget in self
+            .tables
+            .iter()
+            .filter(|b| glob_match(&b.pattern, &table.name))
+        {
+            if let Some(max) = budget.max_size.filter(|&max| table.size_bytes > max) {
+                violations.push(Violation::TableSize {
+                    table: table.name.clone(),
+                    pattern: budget.pattern.clone(),
+                    actual: table.size_bytes,
+                    max,
+                });
+            }
+            if let (Some(max), Some(rows)) = (budget.max_rows, table.known_row_count()) {
+                if rows > max {
+                    violations.push(Violation::Rows {
+                        table: table.name.clone(),
+                        pattern: budget.pattern.clone(),
+                        actual: rows,
+                        max,
+                    });
+                }
+            }
+            if let Some(max) = budget.max_index_ratio {
+                // An empty table with indexes has an infinite ratio
+                let ratio = table.index_size_bytes as f64 / table.size_bytes as f64;
+                if table.index_size_bytes > 0 && ratio > max {
+                    violations.push(Violation::IndexRatio {
+                        table: table.name.clone(),
+                        pattern: budget.pattern.clone(),
+                        actual: ratio,
+                        max,
+                    });
+                }
+            }
+        }
+        violations
+    }
+}
+
+/// Matches `name` against a pattern where `*` stands for any run of characters
+/// and `?` for exactly one.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and how much of the name it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` take one more character and retry
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(feature = "serde")]
+mod file {
+    use std::collections::BTreeMap;
+
+    use serde::Deserialize;
+
+    use crate::format::parse_size;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct BudgetFile {
+        pub max_file_size: Option<Size>,
+        #[serde(default)]
+        pub tables: BTreeMap<String, TableLimits>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub(super) struct TableLimits {
+        pub max_size: Option<Size>,
+        pub max_rows: Option<u64>,
+        pub max_index_ratio: Option<f64>,
+    }
+
+    /// A byte count or a string such as `"500 MB"`.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    pub(super) enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    impl Size {
+        pub fn bytes(&self) -> Result<u64, String> {
+            match self {
+                Size::Bytes(n) => Ok(*n),
+                Size::Text(text) => parse_size(text)
+                    .ok_or_else(|| format!("'{}' is not a size like 500MB or 2GiB", text)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Budget {
+    /// Parses a budget file.
+    pub fn from_toml(text: &str) -> crate::error::Result<Self> {
+        use crate::error::SqduError;
+
+        let file: file::BudgetFile =
+            toml::from_str(text).map_err(|e| SqduError::InvalidBudget(e.to_string()))?;
+        let invalid = |what: &str, e: String| SqduError::InvalidBudget(format!("{}: {}", what, e));
+
+        let max_file_size = match &file.max_file_size {
+            Some(size) => Some(size.bytes().map_err(|e| invalid("max_file_size", e))?),
+            None => None,
+        };
+        let mut tables = Vec::new();
+        for (pattern, limits) in file.tables {
+            let max_size = match &limits.max_size {
+                Some(size) => Some(size.bytes().map_err(|e| invalid(&pattern, e))?),
+                None => None,
+            };
+            tables.push(TableBudget {
+                pattern,
+                max_size,
+                max_rows: limits.max_rows,
+                max_index_ratio: limits.max_index_ratio,
+            });
+        }
+
+        Ok(Self {
+            max_file_size,
+            tables,
+        })
+    }
+
+    pub fn load(path: &std::path::Path) -> crate::error::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+}
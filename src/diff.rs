@@ -0,0 +1,213 @@
+//! Size changes between two analyses, such as yesterday's and today's backup.
+//!
+//! [`DatabaseDiff::between`] matches tables by name and indexes by table and name,
+//! so an object that was renamed shows up as one dropped and one added object.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::app::IndexInfo;
+use crate::report::{DatabaseMetadata, DatabaseReport, TableReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DiffStatus {
+    /// Only in the new database.
+    Added,
+    /// Only in the old database.
+    Dropped,
+    Changed,
+    Unchanged,
+}
+
+/// One number in the old and the new database; `None` where the object does not
+/// exist or the number is not known, such as a row count that was skipped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Change {
+    pub old: Option<u64>,
+    pub new: Option<u64>,
+}
+
+impl Change {
+    pub fn new(old: Option<u64>, new: Option<u64>) -> Self {
+        Self { old, new }
+    }
+
+    /// Growth from old to new, counting a missing side as zero.
+    pub fn delta(&self) -> i64 {
+        self.new.unwrap_or(0) as i64 - self.old.unwrap_or(0) as i64
+    }
+
+    /// Growth in percent of the old value; `None` when there is nothing to compare to.
+    pub fn percent(&self) -> Option<f64> {
+        match (self.old, self.new) {
+            (Some(old), Some(new)) if old > 0 => {
+                Some((new as f64 - old as f64) / old as f64 * 100.0)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A table or index matched across both databases.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ObjectDiff {
+    pub name: String,
+    pub status: DiffStatus,
+    pub size: Change,
+    /// Row counts; `None` for indexes.
+    pub rows: Option<Change>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableDiff {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub table: ObjectDiff,
+    /// Largest change first.
+    pub indexes: Vec<ObjectDiff>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DatabaseDiff {
+    pub old: DatabaseMetadata,
+    pub new: DatabaseMetadata,
+    /// Size of the whole file, in bytes.
+    pub file: Change,
+    /// Bytes on the freelist.
+    pub freelist: Change,
+    /// Largest change first, whether growth or shrinkage.
+    pub tables: Vec<TableDiff>,
+}
+
+impl DatabaseDiff {
+    pub fn between(old: &DatabaseReport, new: &DatabaseReport) -> Self {
+        let old_tables: HashMap<&str, &TableReport> = old
+            .tables
+            .iter()
+            .map(|t| (t.table.name.as_str(), t))
+            .collect();
+        let new_tables: HashMap<&str, &TableReport> = new
+            .tables
+            .iter()
+            .map(|t| (t.table.name.as_str(), t))
+            .collect();
+        let names: BTreeSet<&str> = old_tables
+            .keys()
+            .chain(new_tables.keys())
+            .copied()
+            .collect();
+
+        let mut tables: Vec<TableDiff> = names
+            .into_iter()
+            .map(|name| {
+                let old = old_tables.get(name);
+                let new = new_tables.get(name);
+                TableDiff {
+                    table: object_diff(
+                        name,
+                        Change::new(
+                            old.map(|t| t.table.size_bytes),
+                            new.map(|t| t.table.size_bytes),
+                        ),
+                        Some(Change::new(
+                            old.and_then(|t| t.table.known_row_count()),
+                            new.and_then(|t| t.table.known_row_count()),
+                        )),
+                        old.is_some(),
+                        new.is_some(),
+                    ),
+                    indexes: index_diffs(
+                        old.map_or(&[], |t| &t.indexes),
+                        new.map_or(&[], |t| &t.indexes),
+                    ),
+                }
+            })
+            .collect();
+        tables.sort_by(|a, b| by_change(&a.table, &b.table));
+
+        Self {
+            old: old.database.clone(),
+            new: new.database.clone(),
+            file: Change::new(Some(old.space.file_bytes()), Some(new.space.file_bytes())),
+            freelist: Change::new(
+                Some(old.space.freelist_bytes),
+                Some(new.space.freelist_bytes),
+            ),
+            tables,
+        }
+    }
+
+    /// Number of rows in a listing of every table followed by its indexes.
+    pub fn object_count(&self) -> usize {
+        self.tables.iter().map(|t| 1 + t.indexes.len()).sum()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DatabaseDiff {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+fn index_diffs(old: &[IndexInfo], new: &[IndexInfo]) -> Vec<ObjectDiff> {
+    let old_sizes: HashMap<&str, u64> = old
+        .iter()
+        .map(|i| (i.name.as_str(), i.size_bytes))
+        .collect();
+    let new_sizes: HashMap<&str, u64> = new
+        .iter()
+        .map(|i| (i.name.as_str(), i.size_bytes))
+        .collect();
+    let names: BTreeSet<&str> = old_sizes.keys().chain(new_sizes.keys()).copied().collect();
+
+    let mut diffs: Vec<ObjectDiff> = names
+        .into_iter()
+        .map(|name| {
+            let old = old_sizes.get(name).copied();
+            let new = new_sizes.get(name).copied();
+            object_diff(
+                name,
+                Change::new(old, new),
+                None,
+                old.is_some(),
+                new.is_some(),
+            )
+        })
+        .collect();
+    diffs.sort_by(by_change);
+    diffs
+}
+
+fn object_diff(
+    name: &str,
+    size: Change,
+    rows: Option<Change>,
+    in_old: bool,
+    in_new: bool,
+) -> ObjectDiff {
+    let status = match (in_old, in_new) {
+        (false, _) => DiffStatus::Added,
+        (_, false) => DiffStatus::Dropped,
+        _ if size.old != size.new || rows.is_some_and(|r| r.old != r.new) => DiffStatus::Changed,
+        _ => DiffStatus::Unchanged,
+    };
+    ObjectDiff {
+        name: name.to_string(),
+        status,
+        size,
+        rows,
+    }
+}
+
+fn by_change(a: &ObjectDiff, b: &ObjectDiff) -> std::cmp::Ordering {
+    b.size
+        .delta()
+        .unsigned_abs()
+        .cmp(&a.size.delta().unsigned_abs())
+        .then_with(|| a.name.cmp(&b.name))
+}
@@ -0,0 +1,108 @@
+//! Errors returned by the sqdu library.
+
+use std::fmt;
+use std::io;
+
+use rusqlite::ErrorCode;
+
+pub type Result<T> = std::result::Result<T, SqduError>;
+
+#[derive(Debug)]
+pub enum SqduError {
+    /// The database file does not exist.
+    NotFound(String),
+    /// The file exists but does not start with the SQLite header.
+    NotADatabase(String),
+    /// SQLite was built without `dbstat` and the file could not be read directly
+    /// either.
+    DbstatMissing(io::Error),
+    /// The file or directory cannot be read by this user.
+    PermissionDenied(String),
+    /// Another connection held a lock for longer than the busy timeout.
+    Busy,
+    /// SQLite or the file reader found structural damage.
+    Corrupt(String),
+    /// A budget file could not be parsed.
+    InvalidBudget(String),
+    /// Analysis of a single table or index failed.
+    Object {
+        name: String,
+        source: Box<SqduError>,
+    },
+    Sqlite(rusqlite::Error),
+    Io(io::Error),
+}
+
+impl SqduError {
+    /// Classifies a failure to open the file at `path`.
+    pub fn open(path: &str, err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => SqduError::NotFound(path.to_string()),
+            io::ErrorKind::PermissionDenied => SqduError::PermissionDenied(path.to_string()),
+            _ => SqduError::Io(err),
+        }
+    }
+
+    /// Attributes this error to the table or index `name`.
+    pub fn in_object(self, name: &str) -> Self {
+        SqduError::Object {
+            name: name.to_string(),
+            source: Box::new(self),
+        }
+    }
+}
+
+impl fmt::Display for SqduError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqduError::NotFound(path) => write!(f, "{}: no such file", path),
+            SqduError::NotADatabase(path) => write!(f, "{} is not a SQLite database", path),
+            SqduError::DbstatMissing(err) => write!(
+                f,
+                "SQLite was built without dbstat and the database file could not be read: {}",
+                err
+            ),
+            SqduError::PermissionDenied(what) => write!(f, "{}: permission denied", what),
+            SqduError::Busy => write!(f, "database is locked by another connection"),
+            SqduError::Corrupt(detail) => write!(f, "database is corrupt: {}", detail),
+            SqduError::InvalidBudget(detail) => write!(f, "invalid budget: {}", detail),
+            SqduError::Object { name, source } => write!(f, "{}: {}", name, source),
+            SqduError::Sqlite(err) => write!(f, "{}", err),
+            SqduError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SqduError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SqduError::DbstatMissing(err) | SqduError::Io(err) => Some(err),
+            SqduError::Object { source, .. } => Some(source.as_ref()),
+            SqduError::Sqlite(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for SqduError {
+    fn from(err: rusqlite::Error) -> Self {
+        match err.sqlite_error_code() {
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => SqduError::Busy,
+            Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase) => {
+                SqduError::Corrupt(err.to_string())
+            }
+            Some(ErrorCode::PermissionDenied) => SqduError::PermissionDenied(err.to_string()),
+            _ => SqduError::Sqlite(err),
+        }
+    }
+}
+
+impl From<io::Error> for SqduError {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            // The file reader reports malformed pages as invalid data
+            io::ErrorKind::InvalidData => SqduError::Corrupt(err.to_string()),
+            _ => SqduError::Io(err),
+        }
+    }
+}
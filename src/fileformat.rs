@@ -0,0 +1,282 @@
+//! Minimal reader for the SQLite database file format.
+//!
+//! Used when the linked SQLite library was built without the `dbstat` virtual table.
+//! It parses the 100-byte database header and walks each b-tree from its root page,
+//! producing the same per-object [`PageStats`] that `dbstat` would report. Only the
+//! main database file is read, so changes still sitting in a WAL file are not seen.
+//!
+//! See <https://www.sqlite.org/fileformat2.html> for the layout being decoded here.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::app::PageStats;
+
+pub(crate) const HEADER_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+const INTERIOR_INDEX: u8 = 0x02;
+const INTERIOR_TABLE: u8 = 0x05;
+const LEAF_INDEX: u8 = 0x0a;
+const LEAF_TABLE: u8 = 0x0d;
+
+/// The fields of the database header that matter for space accounting.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub page_size: u32,
+    pub reserved_bytes: u8,
+    pub page_count: u32,
+    pub freelist_count: u32,
+}
+
+impl Header {
+    pub fn parse(bytes: &[u8; 100], file_len: u64) -> io::Result<Self> {
+        if &bytes[..16] != HEADER_MAGIC {
+            return Err(invalid("file is not a SQLite database"));
+        }
+
+        // A stored value of 1 means 65536, which does not fit in two bytes
+        let page_size = match u16::from_be_bytes([bytes[16], bytes[17]]) {
+            1 => 65536,
+            n if n >= 512 && n.is_power_of_two() => n as u32,
+            n => return Err(invalid(&format!("invalid page size {}", n))),
+        };
+
+        // The in-header page count is only trustworthy when it was written by a
+        // library that also bumped the change counter in the same transaction.
+        let change_counter = be_u32(&bytes[24..]);
+        let version_valid_for = be_u32(&bytes[92..]);
+        let header_pages = be_u32(&bytes[28..]);
+        let page_count = if header_pages > 0 && change_counter == version_valid_for {
+            header_pages
+        } else {
+            (file_len / page_size as u64) as u32
+        };
+
+        Ok(Self {
+            page_size,
+            reserved_bytes: bytes[20],
+            page_count,
+            freelist_count: be_u32(&bytes[36..]),
+        })
+    }
+
+    /// Bytes of each page available to b-tree content.
+    pub fn usable_size(&self) -> u32 {
+        self.page_size - self.reserved_bytes as u32
+    }
+}
+
+/// An open database file that b-trees can be measured in.
+pub struct DbFile {
+    file: File,
+    header: Header,
+}
+
+impl DbFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut bytes = [0u8; 100];
+        file.read_exact(&mut bytes)
+            .map_err(|_| invalid("file is too short to be a SQLite database"))?;
+        let header = Header::parse(&bytes, file_len)?;
+
+        Ok(Self { file, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn read_page(&mut self, pgno: u32) -> io::Result<Vec<u8>> {
+        if pgno == 0 || pgno > self.header.page_count {
+            return Err(invalid(&format!("page {} is out of range", pgno)));
+        }
+
+        let page_size = self.header.page_size as u64;
+        let mut page = vec![0u8; page_size as usize];
+        self.file
+            .seek(SeekFrom::Start((pgno as u64 - 1) * page_size))?;
+        self.file.read_exact(&mut page)?;
+        Ok(page)
+    }
+
+    /// Walks the b-tree rooted at `root` and tallies its interior, leaf and
+    /// overflow pages along with the payload and unused bytes on them.
+    pub fn btree_stats(&mut self, root: u32) -> io::Result<PageStats> {
+        let page_size = self.header.page_size as u64;
+        let usable = self.header.usable_size() as u64;
+
+        let mut stats = PageStats::default();
+        let mut visited = HashSet::new();
+        let mut pending = vec![root];
+
+        while let Some(pgno) = pending.pop() {
+            if !visited.insert(pgno) {
+                return Err(invalid(&format!("page {} is referenced twice", pgno)));
+            }
+
+            let page = self.read_page(pgno)?;
+            let hdr = if pgno == 1 { 100 } else { 0 };
+            let page_type = page[hdr];
+            let is_leaf = match page_type {
+                LEAF_TABLE | LEAF_INDEX => true,
+                INTERIOR_TABLE | INTERIOR_INDEX => false,
+                other => {
+                    return Err(invalid(&format!(
+                        "page {} has unknown b-tree page type {:#04x}",
+                        pgno, other
+                    )))
+                }
+            };
+
+            let header_len = if is_leaf { 8 } else { 12 };
+            let cell_count = be_u16(&page[hdr + 3..]) as usize;
+            if hdr + header_len + 2 * cell_count > page.len() {
+                return Err(invalid(&format!("page {} has too many cells", pgno)));
+            }
+            let content_start = match be_u16(&page[hdr + 5..]) {
+                0 => 65536,
+                n => n as u64,
+            };
+
+            // Free space: the gap before the cell content area, fragments and freeblocks
+            let pointers_end = (hdr + header_len + 2 * cell_count) as u64;
+            let mut unused = content_start.saturating_sub(pointers_end) + page[hdr + 7] as u64;
+            let mut freeblock = be_u16(&page[hdr + 1..]) as usize;
+            while freeblock != 0 && freeblock + 4 <= page.len() {
+                unused += be_u16(&page[freeblock + 2..]) as u64;
+                let next = be_u16(&page[freeblock..]) as usize;
+                if next <= freeblock {
+                    break;
+                }
+                freeblock = next;
+            }
+            stats.unused_bytes += unused;
+
+            if is_leaf {
+                stats.leaf_pages += 1;
+                stats.leaf_cells += cell_count as u64;
+                stats.leaf_bytes += page_size;
+            } else {
+                stats.interior_pages += 1;
+                stats.interior_bytes += page_size;
+                pending.push(be_u32(&page[hdr + 8..]));
+            }
+
+            let pointers = &page[hdr + header_len..];
+            for i in 0..cell_count {
+                let mut offset = be_u16(&pointers[2 * i..]) as usize;
+                if offset + 4 > page.len() {
+                    return Err(invalid(&format!("page {} has a bad cell pointer", pgno)));
+                }
+
+                if !is_leaf {
+                    pending.push(be_u32(&page[offset..]));
+                    offset += 4;
+                }
+                if page_type == INTERIOR_TABLE {
+                    // Interior table cells hold only a child pointer and a rowid key
+                    continue;
+                }
+
+                let (payload, n) = read_varint(&page[offset..]);
+                offset += n;
+                if page_type == LEAF_TABLE {
+                    let (_rowid, n) = read_varint(&page[offset..]);
+                    offset += n;
+                }
+
+                let local = local_payload(page_type, payload, usable);
+                stats.payload_bytes += local;
+
+                if local < payload {
+                    let first = offset + local as usize;
+                    if first + 4 > page.len() {
+                        return Err(invalid(&format!("page {} has a truncated cell", pgno)));
+                    }
+                    self.walk_overflow(be_u32(&page[first..]), payload - local, &mut stats)?;
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    fn walk_overflow(
+        &mut self,
+        first: u32,
+        mut remaining: u64,
+        stats: &mut PageStats,
+    ) -> io::Result<()> {
+        let page_size = self.header.page_size as u64;
+        let capacity = self.header.usable_size() as u64 - 4;
+
+        let mut pgno = first;
+        while remaining > 0 && pgno != 0 {
+            let page = self.read_page(pgno)?;
+            let stored = remaining.min(capacity);
+
+            stats.overflow_pages += 1;
+            stats.overflow_bytes += page_size;
+            stats.payload_bytes += stored;
+            stats.unused_bytes += capacity - stored;
+
+            remaining -= stored;
+            pgno = be_u32(&page);
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of payload bytes kept on the b-tree page itself; the rest spills
+/// onto a chain of overflow pages.
+fn local_payload(page_type: u8, payload: u64, usable: u64) -> u64 {
+    let max_local = if page_type == LEAF_TABLE {
+        usable - 35
+    } else {
+        ((usable - 12) * 64 / 255) - 23
+    };
+    if payload <= max_local {
+        return payload;
+    }
+
+    let min_local = ((usable - 12) * 32 / 255) - 23;
+    let local = min_local + (payload - min_local) % (usable - 4);
+    if local <= max_local {
+        local
+    } else {
+        min_local
+    }
+}
+
+/// Decodes a SQLite variable-length integer, returning it with its encoded length.
+fn read_varint(bytes: &[u8]) -> (u64, usize) {
+    let mut value = 0u64;
+    for (i, &byte) in bytes.iter().take(9).enumerate() {
+        if i == 8 {
+            return ((value << 8) | byte as u64, 9);
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return (value, i + 1);
+        }
+    }
+    (value, bytes.len().min(9))
+}
+
+fn be_u16(bytes: &[u8]) -> u16 {
+    u16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
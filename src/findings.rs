@@ -0,0 +1,350 @@
+//! Problems with a schema worth fixing, found by comparing its objects with each
+//! other rather than by measuring them: indexes nothing needs, and foreign keys
+//! no index serves.
+//!
+//! Index definitions come from `PRAGMA index_list` and `PRAGMA index_xinfo`, so
+//! quoting, `COLLATE` and `DESC` are compared the way SQLite sees them.
+
+use rusqlite::Connection;
+
+use crate::app::{ForeignKeyInfo, IndexInfo};
+use crate::error::Result;
+use crate::format::UnitStyle;
+
+/// Why an index is not needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Redundancy {
+    /// Another index has the same columns in the same order.
+    Duplicate,
+    /// Its columns are the leading columns of another index.
+    Prefix,
+    /// A PRIMARY KEY or UNIQUE constraint already indexes its columns.
+    UniqueImplied,
+}
+
+/// An index that can be dropped without making any lookup slower.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RedundantIndex {
+    pub table: String,
+    pub index: String,
+    pub reason: Redundancy,
+    /// The index that makes this one unnecessary; `INTEGER PRIMARY KEY` for
+    /// the rowid.
+    pub covered_by: String,
+    /// Size of the redundant index.
+    pub reclaimable_bytes: u64,
+}
+
+/// A foreign key whose columns no index starts with, so every delete from the
+/// parent table, or change to its key, scans the whole child table.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnindexedForeignKey {
+    pub table: String,
+    /// In key order; more than one for a composite key.
+    pub columns: Vec<String>,
+    pub to_table: String,
+    /// The parent columns `columns` refer to, in the same order; empty when
+    /// the key refers to the parent's primary key without naming it.
+    pub to_cols: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "finding", rename_all = "snake_case"))]
+pub enum Finding {
+    RedundantIndex(RedundantIndex),
+    UnindexedForeignKey(UnindexedForeignKey),
+}
+
+impl Finding {
+    pub fn table(&self) -> &str {
+        match self {
+            Finding::RedundantIndex(r) => &r.table,
+            Finding::UnindexedForeignKey(fk) => &fk.table,
+        }
+    }
+
+    /// Bytes that fixing this would free.
+    pub fn reclaimable_bytes(&self) -> u64 {
+        match self {
+            Finding::RedundantIndex(r) => r.reclaimable_bytes,
+            Finding::UnindexedForeignKey(_) => 0,
+        }
+    }
+
+    /// One line for a list of findings.
+    pub fn describe(&self, units: UnitStyle) -> String {
+        match self {
+            Finding::RedundantIndex(r) => format!(
+                "{}: {}; dropping it reclaims {}",
+                r.index,
+                r.explain(),
+                units.format(r.reclaimable_bytes)
+            ),
+            Finding::UnindexedForeignKey(fk) => {
+                let target = match &fk.to_cols[..] {
+                    [] => fk.to_table.clone(),
+                    [to_col] => format!("{}.{}", fk.to_table, to_col),
+                    to_cols => format!("{}({})", fk.to_table, to_cols.join(", ")),
+                };
+                match &fk.columns[..] {
+                    [column] => format!(
+                        "{}: foreign key to {} has no index starting with it",
+                        column, target
+                    ),
+                    columns => format!(
+                        "({}): foreign key to {} has no index starting with these columns",
+                        columns.join(", "),
+                        target
+                    ),
+                }
+            }
+        }
+    }
+}
+
+impl RedundantIndex {
+    /// What makes the index unnecessary, e.g. "duplicate of idx_b".
+    pub fn explain(&self) -> String {
+        match self.reason {
+            Redundancy::Duplicate => format!("duplicate of {}", self.covered_by),
+            Redundancy::Prefix => format!("prefix of {}", self.covered_by),
+            Redundancy::UniqueImplied => format!("already indexed by {}", self.covered_by),
+        }
+    }
+}
+
+/// One key column of an index as `PRAGMA index_xinfo` reports it.
+#[derive(Debug, Clone, PartialEq)]
+struct KeyColumn {
+    /// Column number in the table; -2 for an expression.
+    cid: i64,
+    /// `None` for an expression.
+    name: Option<String>,
+    desc: bool,
+    collation: String,
+}
+
+struct IndexKeys {
+    name: String,
+    unique: bool,
+    partial: bool,
+    /// Created by a PRIMARY KEY or UNIQUE constraint rather than CREATE INDEX;
+    /// such indexes cannot be dropped on their own.
+    constraint: bool,
+    keys: Vec<KeyColumn>,
+}
+
+impl IndexKeys {
+    /// Expressions cannot be compared from the pragmas alone.
+    fn comparable(&self) -> bool {
+        !self.keys.iter().any(|k| k.cid == -2)
+    }
+}
+
+fn index_keys(conn: &Connection, table_name: &str) -> Result<Vec<IndexKeys>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, \"unique\", origin, partial FROM pragma_index_list(?1) ORDER BY name",
+    )?;
+    let listed = stmt
+        .query_map([table_name], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, bool>(3)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT cid, name, \"desc\", coll FROM pragma_index_xinfo(?1) WHERE key ORDER BY seqno",
+    )?;
+    let mut indexes = Vec::new();
+    for (name, unique, origin, partial) in listed {
+        let keys = stmt
+            .query_map([&name], |row| {
+                Ok(KeyColumn {
+                    cid: row.get(0)?,
+                    name: row.get(1)?,
+                    desc: row.get(2)?,
+                    collation: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        indexes.push(IndexKeys {
+            name,
+            unique,
+            partial,
+            constraint: origin != "c",
+            keys,
+        });
+    }
+    Ok(indexes)
+}
+
+/// The INTEGER PRIMARY KEY column as an index, since it is the rowid and every
+/// lookup by it already uses the table's own b-tree.
+fn rowid_key(
+    conn: &Connection,
+    table_name: &str,
+    indexes: &[IndexKeys],
+) -> Result<Option<IndexKeys>> {
+    // table_xinfo, unlike table_info, numbers columns the way index_xinfo does
+    let mut stmt =
+        conn.prepare("SELECT cid, name, type FROM pragma_table_xinfo(?1) WHERE pk > 0")?;
+    let pk = stmt
+        .query_map([table_name], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // A composite, non-INTEGER or WITHOUT ROWID key has an index of its own
+    let [(cid, name, col_type)] = pk.as_slice() else {
+        return Ok(None);
+    };
+    let has_pk_index = indexes
+        .iter()
+        .any(|i| i.constraint && i.keys.len() == 1 && i.keys[0].cid == *cid);
+    if !col_type.eq_ignore_ascii_case("INTEGER") || has_pk_index {
+        return Ok(None);
+    }
+    Ok(Some(IndexKeys {
+        name: String::from("INTEGER PRIMARY KEY"),
+        unique: true,
+        partial: false,
+        constraint: true,
+        keys: vec![KeyColumn {
+            cid: *cid,
+            name: Some(name.clone()),
+            desc: false,
+            collation: String::from("BINARY"),
+        }],
+    }))
+}
+
+/// Whether `other` makes `index` unnecessary, and why.
+fn covered(index: &IndexKeys, other: &IndexKeys) -> Option<Redundancy> {
+    if other.name == index.name || other.partial || !other.comparable() {
+        return None;
+    }
+    let equal = index.keys == other.keys;
+    let prefix = index.keys.len() < other.keys.len() && other.keys.starts_with(&index.keys);
+    let redundant = if equal {
+        // Of two equal indexes keep the one enforcing uniqueness, then the
+        // constraint's, then the full one, then the first by name
+        match (index.unique, other.unique) {
+            (true, false) => false,
+            (false, true) => true,
+            _ => other.constraint || index.partial || other.name < index.name,
+        }
+    } else {
+        // A UNIQUE index enforces something even when a longer one covers it
+        prefix && !index.unique
+    };
+
+    match redundant {
+        false => None,
+        true if other.constraint => Some(Redundancy::UniqueImplied),
+        true if equal => Some(Redundancy::Duplicate),
+        true => Some(Redundancy::Prefix),
+    }
+}
+
+/// Indexes of one table that duplicate, or are a prefix of, another index or
+/// constraint. `indexes` provides the sizes; partial indexes are only ever
+/// reported as covered by a full one, and expression indexes not at all.
+pub fn redundant_indexes(
+    conn: &Connection,
+    table_name: &str,
+    indexes: &[IndexInfo],
+) -> Result<Vec<RedundantIndex>> {
+    let mut keys = index_keys(conn, table_name)?;
+    if let Some(rowid) = rowid_key(conn, table_name, &keys)? {
+        keys.push(rowid);
+    }
+
+    let mut redundant = Vec::new();
+    for index in keys.iter().filter(|i| !i.constraint && i.comparable()) {
+        // Prefer an equal index, then constraints, then the longest index,
+        // which is the one least likely to be redundant itself
+        let cover = keys
+            .iter()
+            .filter_map(|other| Some((covered(index, other)?, other)))
+            .min_by_key(|(_, other)| {
+                (
+                    other.keys.len() != index.keys.len(),
+                    !other.constraint,
+                    std::cmp::Reverse(other.keys.len()),
+                    &other.name,
+                )
+            });
+        if let Some((reason, other)) = cover {
+            redundant.push(RedundantIndex {
+                table: table_name.to_string(),
+                index: index.name.clone(),
+                reason,
+                covered_by: other.name.clone(),
+                reclaimable_bytes: indexes
+                    .iter()
+                    .find(|i| i.name == index.name)
+                    .map_or(0, |i| i.size_bytes),
+            });
+        }
+    }
+    Ok(redundant)
+}
+
+/// Foreign keys of one table whose columns are not the leading columns of any
+/// full index, in any order, counting the INTEGER PRIMARY KEY as one.
+pub fn unindexed_foreign_keys(
+    conn: &Connection,
+    table_name: &str,
+    foreign_keys: &[ForeignKeyInfo],
+) -> Result<Vec<UnindexedForeignKey>> {
+    let mut keys = index_keys(conn, table_name)?;
+    if let Some(rowid) = rowid_key(conn, table_name, &keys)? {
+        keys.push(rowid);
+    }
+    // A partial index only serves lookups its WHERE clause allows
+    let indexes: Vec<&IndexKeys> = keys.iter().filter(|i| !i.partial).collect();
+
+    let mut unindexed = Vec::new();
+    // The columns of a composite key are listed one after another
+    for fk in foreign_keys.chunk_by(|a, b| a.id == b.id) {
+        let indexed = indexes.iter().any(|i| {
+            i.keys.len() >= fk.len()
+                && i.keys[..fk.len()].iter().all(|key| {
+                    key.name.as_deref().is_some_and(|name| {
+                        fk.iter().any(|c| c.from_col.eq_ignore_ascii_case(name))
+                    })
+                })
+        });
+        if !indexed {
+            unindexed.push(UnindexedForeignKey {
+                table: table_name.to_string(),
+                columns: fk.iter().map(|c| c.from_col.clone()).collect(),
+                to_table: fk[0].to_table.clone(),
+                to_cols: fk.iter().filter_map(|c| c.to_col.clone()).collect(),
+            });
+        }
+    }
+    Ok(unindexed)
+}
+
+/// Orders findings by the bytes fixing them would free, most first, then by table.
+pub fn sort_findings(findings: &mut [Finding]) {
+    findings.sort_by(|a, b| {
+        b.reclaimable_bytes()
+            .cmp(&a.reclaimable_bytes())
+            .then_with(|| a.table().cmp(b.table()))
+    });
+}
@@ -0,0 +1,118 @@
+//! Sizes and counts written as text, shared by the interface and the reports.
+
+use crate::app::{RowCount, TableInfo};
+
+pub fn format_number(n: u64) -> String {
+    let s = n.to_string();
+    let mut result = String::new();
+
+    for (count, c) in s.chars().rev().enumerate() {
+        if count > 0 && count % 3 == 0 {
+            result.push(',');
+        }
+        result.push(c);
+    }
+
+    result.chars().rev().collect()
+}
+
+/// How byte sizes are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnitStyle {
+    /// Powers of 1024: KiB, MiB, GiB.
+    #[default]
+    Binary,
+    /// Powers of 1000: kB, MB, GB.
+    Decimal,
+    /// Exact byte counts with thousands separators.
+    Bytes,
+}
+
+impl UnitStyle {
+    /// Every style, by the name used on the command line.
+    pub const ALL: &'static [(&'static str, UnitStyle)] = &[
+        ("binary", UnitStyle::Binary),
+        ("decimal", UnitStyle::Decimal),
+        ("bytes", UnitStyle::Bytes),
+    ];
+
+    pub fn format(self, bytes: u64) -> String {
+        let (base, units): (f64, &[&str]) = match self {
+            UnitStyle::Binary => (1024.0, &["B", "KiB", "MiB", "GiB", "TiB"]),
+            UnitStyle::Decimal => (1000.0, &["B", "kB", "MB", "GB", "TB"]),
+            UnitStyle::Bytes => return format!("{} B", format_number(bytes)),
+        };
+        let mut size = bytes as f64;
+        let mut unit_index = 0;
+
+        while size >= base && unit_index < units.len() - 1 {
+            size /= base;
+            unit_index += 1;
+        }
+
+        if unit_index == 0 {
+            format!("{} {}", bytes, units[0])
+        } else {
+            format!("{:.1} {}", size, units[unit_index])
+        }
+    }
+
+    /// A size change with its sign, e.g. `+1.5 KiB` or `-200 B`.
+    pub fn format_delta(self, delta: i64) -> String {
+        let sign = match delta.signum() {
+            1 => "+",
+            -1 => "-",
+            _ => "",
+        };
+        format!("{}{}", sign, self.format(delta.unsigned_abs()))
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    UnitStyle::Binary.format(bytes)
+}
+
+/// Reads a size such as `500`, `64KiB`, `1.5 GB` or `10M`. Suffixes are
+/// case-insensitive; `kB`/`MB`/`GB`/`TB` are powers of 1000, while `KiB`/`MiB`
+/// and the single letters `K`/`M`/`G`/`T` are powers of 1024.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000_u64.pow(2),
+        "gb" => 1000_u64.pow(3),
+        "tb" => 1000_u64.pow(4),
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * multiplier as f64).round() as u64)
+}
+
+/// A change in a count with its sign, e.g. `+1,024`.
+pub fn format_signed(n: i64) -> String {
+    let sign = match n.signum() {
+        1 => "+",
+        -1 => "-",
+        _ => "",
+    };
+    format!("{}{}", sign, format_number(n.unsigned_abs()))
+}
+
+/// Row count of a table, or a marker while it is still being (or was never) counted.
+pub fn format_row_count(table: &TableInfo) -> String {
+    match table.row_count_kind {
+        RowCount::Exact => format_number(table.row_count),
+        RowCount::Estimated => format!("~{}", format_number(table.row_count)),
+        RowCount::Pending => String::from("..."),
+        RowCount::Skipped => String::from("?"),
+    }
+}
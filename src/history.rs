@@ -0,0 +1,243 @@
+//! Saved analyses of a database and how its tables grew between them.
+//!
+//! With the `serde` feature a [`HistoryStore`] keeps one JSON line per recorded
+//! analysis, for any number of databases, in a single append-only file. Loading it
+//! for one database gives a [`History`], from which [`Series`] of table sizes are
+//! drawn as sparklines and extrapolated into growth rates and projections.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "serde")]
+use std::fs::{self, OpenOptions};
+#[cfg(feature = "serde")]
+use std::io::{self, BufRead, BufReader, Write};
+#[cfg(feature = "serde")]
+use std::path::{Path, PathBuf};
+
+use crate::app::{IndexInfo, TableInfo};
+#[cfg(feature = "serde")]
+use crate::error::Result;
+use crate::report::DatabaseReport;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// One recorded analysis of one database.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistoryEntry {
+    /// Seconds since the Unix epoch.
+    pub recorded_at: u64,
+    /// Canonical path of the database, which is what entries are looked up by.
+    pub database: String,
+    pub file_size: u64,
+    pub tables: Vec<TableInfo>,
+    /// Indexes by table name.
+    pub indexes: BTreeMap<String, Vec<IndexInfo>>,
+}
+
+impl HistoryEntry {
+    pub fn from_report(report: &DatabaseReport) -> Self {
+        Self {
+            recorded_at: report.generated_at,
+            database: database_key(&report.database.path),
+            file_size: report.database.file_size,
+            tables: report.tables.iter().map(|t| t.table.clone()).collect(),
+            indexes: report
+                .tables
+                .iter()
+                .map(|t| (t.table.name.clone(), t.indexes.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// The key a database's entries are stored under, the same for every way of
+/// spelling its path.
+pub fn database_key(db_path: &str) -> String {
+    std::fs::canonicalize(db_path)
+        .ok()
+        .and_then(|p| p.to_str().map(str::to_string))
+        .unwrap_or_else(|| db_path.to_string())
+}
+
+/// The recorded analyses of one database, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Sizes of `table` at every recorded analysis that has it.
+    pub fn series(&self, table: &str) -> Series {
+        let points = self
+            .entries
+            .iter()
+            .filter_map(|e| {
+                let t = e.tables.iter().find(|t| t.name == table)?;
+                Some((e.recorded_at, t.size_bytes))
+            })
+            .collect();
+        Series { points }
+    }
+}
+
+/// Sizes of one object over time as `(seconds since the epoch, bytes)`, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Series {
+    pub points: Vec<(u64, u64)>,
+}
+
+impl Series {
+    /// The series with one more, newer, point; used to add the live analysis to
+    /// what was recorded before.
+    pub fn with_point(mut self, at: u64, bytes: u64) -> Self {
+        self.points.retain(|&(t, _)| t < at);
+        self.points.push((at, bytes));
+        self
+    }
+
+    /// Average growth in bytes per day, from a least-squares line through all
+    /// points. `None` until there are two points some time apart.
+    pub fn growth_per_day(&self) -> Option<f64> {
+        let (first, last) = (self.points.first()?.0, self.points.last()?.0);
+        if last <= first {
+            return None;
+        }
+
+        // Days since the first point keep the sums small enough for f64
+        let n = self.points.len() as f64;
+        let xs: Vec<f64> = self
+            .points
+            .iter()
+            .map(|&(t, _)| (t - first) as f64 / SECONDS_PER_DAY)
+            .collect();
+        let mean_x = xs.iter().sum::<f64>() / n;
+        let mean_y = self.points.iter().map(|&(_, b)| b as f64).sum::<f64>() / n;
+
+        let mut covariance = 0.0;
+        let mut variance = 0.0;
+        for (x, &(_, bytes)) in xs.iter().zip(&self.points) {
+            covariance += (x - mean_x) * (bytes as f64 - mean_y);
+            variance += (x - mean_x) * (x - mean_x);
+        }
+        Some(covariance / variance)
+    }
+
+    /// When the object reaches `target` bytes if it keeps growing at
+    /// [`Series::growth_per_day`], as seconds since the epoch. `None` when it is
+    /// not growing or is already that large.
+    pub fn projected_time(&self, target: u64) -> Option<u64> {
+        let &(at, bytes) = self.points.last()?;
+        let per_day = self.growth_per_day()?;
+        if bytes >= target || per_day <= 0.0 {
+            return None;
+        }
+        let days = (target - bytes) as f64 / per_day;
+        Some(at.saturating_add((days * SECONDS_PER_DAY) as u64))
+    }
+
+    /// The last `width` sizes as block characters scaled between the smallest
+    /// and the largest of them.
+    pub fn sparkline(&self, width: usize) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+        let recent = &self.points[self.points.len().saturating_sub(width)..];
+        let min = recent.iter().map(|&(_, b)| b).min().unwrap_or(0);
+        let max = recent.iter().map(|&(_, b)| b).max().unwrap_or(0);
+        recent
+            .iter()
+            .map(|&(_, bytes)| {
+                if max == min {
+                    BARS[0]
+                } else {
+                    let level = (bytes - min) as f64 / (max - min) as f64;
+                    BARS[(level * (BARS.len() - 1) as f64).round() as usize]
+                }
+            })
+            .collect()
+    }
+}
+
+/// `YYYY-MM-DD` in UTC for seconds since the epoch.
+pub fn format_date(secs: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// An append-only file with one [`HistoryEntry`] per line.
+#[cfg(feature = "serde")]
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+#[cfg(feature = "serde")]
+impl HistoryStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// `$XDG_DATA_HOME/sqdu/history.jsonl`, or under `~/.local/share` when
+    /// `XDG_DATA_HOME` is not set.
+    pub fn default_path() -> Option<PathBuf> {
+        let data = std::env::var_os("XDG_DATA_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/share")))?;
+        Some(data.join("sqdu").join("history.jsonl"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, entry: &HistoryEntry) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        line.push('\n');
+        // One write per entry, so concurrent runs do not interleave lines
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Every entry recorded for `db_path`, oldest first. A store that does
+    /// not exist yet is empty; lines that cannot be read, such as one cut
+    /// short by a crash, are skipped.
+    pub fn load(&self, db_path: &str) -> Result<History> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(History::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let key = database_key(db_path);
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            if let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line?) {
+                if entry.database == key {
+                    entries.push(entry);
+                }
+            }
+        }
+        entries.sort_by_key(|e| e.recorded_at);
+        Ok(History { entries })
+    }
+}
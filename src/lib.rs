@@ -1,2 +1,15 @@
+pub mod analyzer;
 pub mod app;
+pub mod budget;
+pub mod diff;
+pub mod error;
+pub mod fileformat;
+pub mod findings;
+pub mod format;
+pub mod history;
+pub mod output;
+pub mod report;
+pub mod snapshot;
 pub mod ui;
+pub mod worker;
+pub mod workload;
@@ -1,19 +1,42 @@
-mod app;
-mod ui;
-
-use anyhow::Result;
+use anyhow::{Context, Result};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
-use std::{env, io};
+use std::io::{self, IsTerminal, Stdout, Write};
+use std::path::PathBuf;
+use std::{fs, time::Duration};
+
+use sqdu::analyzer::Analyzer;
+use sqdu::app::{AnalysisOptions, App, RowCountMode, SortKey, ViewMode};
+use sqdu::diff::DatabaseDiff;
+use sqdu::format::{parse_size, UnitStyle};
+use sqdu::output::{render, render_diff, write_atomically, OutputFormat};
+use sqdu::report::DatabaseReport;
+use sqdu::ui::ui;
+use sqdu::worker::{spawn_analysis, spawn_row_counts, AnalysisHandle};
+use sqdu::workload::{IndexUsage, Workload};
+#[cfg(feature = "serde")]
+use sqdu::{
+    app::AnalysisState,
+    budget::Budget,
+    history::{database_key, HistoryEntry, HistoryStore},
+};
 
-use app::{analyze_database, analyze_indexes, analyze_table_details, App, ViewMode};
-use ui::ui;
+const NO_DBSTAT_WARNING: &str = "SQLite was built without dbstat: sizes come from sqdu's file \
+     reader and exclude un-checkpointed WAL changes (build with --features bundled)";
 
-fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<()> {
+fn run_app<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    mut analysis: AnalysisHandle,
+    analyzer: &mut Analyzer,
+) -> Result<()> {
     let mut prev_view_mode: Option<String> = None;
     let mut prev_scroll_offset: u16 = 0;
 
@@ -29,11 +52,44 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
             prev_scroll_offset = app.scroll_offset;
         }
 
-        terminal.draw(|f| ui(f, &mut app))?;
+        terminal.draw(|f| ui(f, app))?;
+
+        // Wake up regularly to pick up results from the background analysis
+        while let Ok(event) = analysis.events.try_recv() {
+            app.apply(event);
+        }
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
 
         if let Event::Key(key) = event::read()? {
             match key.code {
-                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('q') => {
+                    analysis.cancel();
+                    return Ok(());
+                }
+                KeyCode::Esc if app.analysis.is_running() => analysis.cancel(),
+                KeyCode::Char('s') if app.analysis.is_running() => analysis.skip_current(),
+                KeyCode::Char(c @ ('c' | 'C')) if !app.analysis.is_running() => {
+                    // Replace estimates with exact counts for the selected or all tables
+                    if let ViewMode::Tables = app.view_mode {
+                        let names: Vec<String> = if c == 'C' {
+                            app.tables.iter().map(|t| t.name.clone()).collect()
+                        } else {
+                            app.selected_table()
+                                .map(|t| vec![t.name.clone()])
+                                .unwrap_or_default()
+                        };
+                        if !names.is_empty() {
+                            app.start_counting(names.len());
+                            analysis = spawn_row_counts(
+                                analyzer.db_path().to_string(),
+                                names,
+                                analyzer.options(),
+                            );
+                        }
+                    }
+                }
                 KeyCode::Down | KeyCode::Char('j') => match app.view_mode {
                     ViewMode::TableInfo(_) => app.scroll_down(),
                     _ => app.next(),
@@ -50,9 +106,12 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
                             if i >= 2 {
                                 if let Some(table) = app.tables.get(i - 2) {
                                     // Analyze indexes for this table
-                                    match analyze_indexes(&app.db_path, &table.name) {
+                                    match analyzer.indexes(&table.name) {
                                         Ok(indexes) => {
-                                            app.indexes = indexes;
+                                            app.indexes = indexes.to_vec();
+                                            app.redundant = analyzer
+                                                .redundant_indexes(&table.name)
+                                                .unwrap_or_default();
                                             app.view_mode = ViewMode::Indexes(table.name.clone());
                                             app.list_state.select(if app.indexes.is_empty() {
                                                 None
@@ -77,9 +136,12 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
                                 // Subtract 2 to account for header rows
                                 if i >= 2 {
                                     if let Some(table) = app.tables.get(i - 2) {
-                                        match analyze_table_details(&app.db_path, &table.name) {
+                                        match analyzer.table_details(&table.name) {
                                             Ok(details) => {
-                                                app.table_details = Some(details);
+                                                app.table_details = Some(details.clone());
+                                                app.unindexed = analyzer
+                                                    .unindexed_foreign_keys(&table.name)
+                                                    .unwrap_or_default();
                                                 app.view_mode =
                                                     ViewMode::TableInfo(table.name.clone());
                                                 // Clear list state to avoid artifacts
@@ -96,9 +158,12 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
                         }
                         ViewMode::Indexes(table_name) => {
                             // From indexes view, show table info
-                            match analyze_table_details(&app.db_path, table_name) {
+                            match analyzer.table_details(table_name) {
                                 Ok(details) => {
-                                    app.table_details = Some(details);
+                                    app.table_details = Some(details.clone());
+                                    app.unindexed = analyzer
+                                        .unindexed_foreign_keys(table_name)
+                                        .unwrap_or_default();
                                     app.view_mode = ViewMode::TableInfo(table_name.clone());
                                     // Clear list state to avoid artifacts
                                     app.list_state.select(None);
@@ -109,19 +174,33 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
                                 }
                             }
                         }
-                        ViewMode::TableInfo(_) => {
+                        ViewMode::TableInfo(_) | ViewMode::Diff | ViewMode::Findings => {
                             // Already in info view, do nothing
                         }
                     }
                 }
+                KeyCode::Char('f') if !app.analysis.is_running() => {
+                    // Redundant indexes and other problems across all tables
+                    if let ViewMode::Tables = app.view_mode {
+                        if let Ok(findings) = analyzer.findings() {
+                            app.findings = findings;
+                            app.view_mode = ViewMode::Findings;
+                            app.list_state.select(if app.findings.is_empty() {
+                                None
+                            } else {
+                                Some(2) // Start at first real item after headers
+                            });
+                        }
+                    }
+                }
                 KeyCode::Backspace | KeyCode::Left | KeyCode::Char('h') => {
                     // Go back to tables view
                     match &app.view_mode {
-                        ViewMode::Indexes(_) | ViewMode::TableInfo(_) => {
+                        ViewMode::Indexes(_) | ViewMode::TableInfo(_) | ViewMode::Findings => {
                             app.view_mode = ViewMode::Tables;
                             app.list_state.select(Some(2)); // Start at first real item after headers
                         }
-                        ViewMode::Tables => {
+                        ViewMode::Tables | ViewMode::Diff => {
                             // Already at top level
                         }
                     }
@@ -132,23 +211,499 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, mut app: Ap
     }
 }
 
+/// Browses a [`DatabaseDiff`]; everything is known up front, so there is no
+/// analysis to follow.
+fn run_diff_app<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+) -> Result<()> {
+    loop {
+        terminal.draw(|f| ui(f, &mut app))?;
+
+        if let Event::Key(key) = event::read()? {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Down | KeyCode::Char('j') => app.next(),
+                KeyCode::Up | KeyCode::Char('k') => app.previous(),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Disk usage analyzer for SQLite databases
+#[derive(Parser)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    after_help = "Without a subcommand, sqdu opens the interface, or prints a report when \
+                  --format or --output is given or stdout is not a terminal."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// SQLite database to analyze
+    #[arg(value_name = "DATABASE", required = true)]
+    database: Option<String>,
+
+    #[command(flatten)]
+    analysis: AnalysisArgs,
+
+    #[command(flatten)]
+    overview: OverviewArgs,
+
+    #[command(flatten)]
+    interface: InterfaceArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Browse the database interactively
+    Tui {
+        /// SQLite database to analyze
+        database: String,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+        #[command(flatten)]
+        overview: OverviewArgs,
+        #[command(flatten)]
+        interface: InterfaceArgs,
+    },
+    /// Print a report and exit
+    Report {
+        /// SQLite database to analyze
+        database: String,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+        #[command(flatten)]
+        overview: OverviewArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Compare table and index sizes of two databases, e.g. two backups
+    Diff {
+        /// The earlier database
+        old: String,
+        /// The later database
+        new: String,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Check databases against a budget file, exiting with status 1 when any
+    /// is over budget
+    #[cfg(feature = "serde")]
+    Check {
+        /// SQLite databases to check
+        #[arg(required = true)]
+        databases: Vec<String>,
+        /// Budget file of maximum sizes
+        #[arg(long, value_name = "FILE")]
+        budget: PathBuf,
+        #[command(flatten)]
+        analysis: AnalysisArgs,
+    },
+}
+
+/// Options shared by every subcommand.
+#[derive(Args)]
+struct AnalysisArgs {
+    /// Skip COUNT(*) and estimate rows from the b-tree leaf cells
+    #[arg(long)]
+    estimate: bool,
+
+    /// Count rows on this many connections at once [default: one per core]
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+
+    /// Open with immutable=1 to read files on read-only media without taking
+    /// locks (connections are always read-only)
+    #[arg(long)]
+    immutable: bool,
+
+    /// Analyze a consistent copy of a database that is being written to
+    #[arg(long)]
+    snapshot: bool,
+
+    /// Wait this long for writers before giving up
+    #[arg(long, value_name = "MS", default_value_t = 5000)]
+    busy_timeout: u64,
+
+    /// Also list SQLite's own sqlite_* tables
+    #[arg(long)]
+    include_internal: bool,
+
+    /// Leave out the indexes SQLite creates for PRIMARY KEY and UNIQUE constraints
+    #[arg(long)]
+    hide_auto_indexes: bool,
+
+    /// How sizes are written
+    #[arg(short, long, value_name = "STYLE", default_value = "binary", value_parser = named(UnitStyle::ALL))]
+    units: UnitStyle,
+}
+
+impl AnalysisArgs {
+    fn options(&self) -> AnalysisOptions {
+        let mut options = AnalysisOptions::default();
+        if self.estimate {
+            options.row_counts = RowCountMode::Estimate;
+        }
+        if let Some(jobs) = self.jobs {
+            options.jobs = jobs as usize;
+        }
+        options.open.immutable = self.immutable;
+        options.open.busy_timeout = Duration::from_millis(self.busy_timeout);
+        options.snapshot = self.snapshot;
+        options.include_internal = self.include_internal;
+        options.hide_auto_indexes = self.hide_auto_indexes;
+        options
+    }
+
+    /// Opens `db_path` for analysis, warning on stderr about anything that
+    /// makes the numbers less reliable.
+    fn open(&self, db_path: &str) -> Result<Analyzer> {
+        let options = self.options();
+        if options.snapshot {
+            eprintln!("Taking a snapshot of {}...", db_path);
+        }
+        let analyzer = Analyzer::open(db_path, options)?;
+        if !analyzer.has_dbstat() {
+            eprintln!("Warning: {}", NO_DBSTAT_WARNING);
+        }
+        Ok(analyzer)
+    }
+}
+
+/// Options for the table overview of a single database, browsed or printed.
+#[derive(Args)]
+struct OverviewArgs {
+    /// Order of the tables
+    #[arg(short, long, value_name = "KEY", default_value = "size", value_parser = named(SortKey::ALL))]
+    sort: SortKey,
+
+    /// Save this analysis to the history store
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    record: bool,
+
+    /// History store to show growth from and record to
+    /// [default: $XDG_DATA_HOME/sqdu/history.jsonl]
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE")]
+    history: Option<PathBuf>,
+}
+
+impl OverviewArgs {
+    #[cfg(feature = "serde")]
+    fn history_store(&self) -> Option<HistoryStore> {
+        self.history
+            .clone()
+            .or_else(HistoryStore::default_path)
+            .map(HistoryStore::new)
+    }
+}
+
+/// Options only the interface shows anything for.
+#[derive(Args)]
+struct InterfaceArgs {
+    /// Size to project table growth to, e.g. 10GiB [default: twice the current size]
+    #[arg(long, value_name = "SIZE", value_parser = size)]
+    target_size: Option<u64>,
+
+    /// Budget file of maximum sizes to highlight tables over
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE")]
+    budget: Option<PathBuf>,
+
+    /// File of SQL statements, e.g. a query log, to mark indexes used or never
+    /// used by
+    #[arg(long, value_name = "FILE")]
+    workload: Option<PathBuf>,
+}
+
+impl InterfaceArgs {
+    /// Whether any option was given, which a printed report would ignore.
+    fn any(&self) -> bool {
+        #[cfg(feature = "serde")]
+        if self.budget.is_some() {
+            return true;
+        }
+        self.target_size.is_some() || self.workload.is_some()
+    }
+
+    #[cfg(feature = "serde")]
+    fn budget(&self) -> Result<Option<Budget>> {
+        self.budget.as_deref().map(load_budget).transpose()
+    }
+
+    /// How often the statements of `--workload` use each index of the database.
+    fn index_usage(&self, analyzer: &Analyzer) -> Result<Option<IndexUsage>> {
+        let Some(path) = &self.workload else {
+            return Ok(None);
+        };
+        let workload =
+            Workload::load(path).with_context(|| format!("Cannot read {}", path.display()))?;
+        eprintln!("Planning {} statements...", workload.statements.len());
+        Ok(Some(analyzer.index_usage(&workload)?))
+    }
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// Report format [default: text]
+    #[arg(short, long, value_name = "FORMAT", value_parser = named(OutputFormat::ALL))]
+    format: Option<OutputFormat>,
+
+    /// Write the report to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<PathBuf>,
+}
+
+impl OutputArgs {
+    /// The format to print in, or `None` to open the interface instead.
+    fn format_or_tui(&self) -> Option<OutputFormat> {
+        // Without a terminal to draw on, fall back to a plain text report
+        match self.format {
+            None if self.output.is_some() || !io::stdout().is_terminal() => {
+                Some(OutputFormat::Text)
+            }
+            format => format,
+        }
+    }
+
+    fn write(&self, text: String) -> Result<()> {
+        match &self.output {
+            // Replaced in one step so monitoring never reads half a report, unless
+            // it is something like /dev/stderr that cannot be replaced
+            Some(path) if !path.exists() || path.is_file() => write_atomically(path, &text)
+                .with_context(|| format!("Cannot write {}", path.display()))?,
+            Some(path) => {
+                fs::write(path, text).with_context(|| format!("Cannot write {}", path.display()))?
+            }
+            None => io::stdout().write_all(text.as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+fn load_budget(path: &std::path::Path) -> Result<Budget> {
+    Budget::load(path).with_context(|| format!("Cannot read {}", path.display()))
+}
+
+/// Accepts sizes such as `512MB` or `10GiB`.
+fn size(text: &str) -> std::result::Result<u64, String> {
+    parse_size(text).ok_or_else(|| format!("'{}' is not a size like 512MB or 10GiB", text))
+}
+
+/// Accepts the names of one of the `ALL` tables of the library's option types.
+fn named<T: Copy + Send + Sync + 'static>(
+    all: &'static [(&'static str, T)],
+) -> impl TypedValueParser<Value = T> {
+    PossibleValuesParser::new(all.iter().map(|(name, _)| *name))
+        .map(move |s| all.iter().find(|(name, _)| *name == s).unwrap().1)
+}
+
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <database.db>", args[0]);
-        std::process::exit(1);
+    let cli = Cli::parse();
+
+    match cli.command {
+        Some(Command::Tui {
+            database,
+            analysis,
+            overview,
+            interface,
+        }) => run_tui(&database, &analysis, &overview, &interface),
+        Some(Command::Report {
+            database,
+            analysis,
+            overview,
+            output,
+        }) => run_report(
+            &database,
+            output.format.unwrap_or(OutputFormat::Text),
+            &analysis,
+            &overview,
+            &output,
+        ),
+        Some(Command::Diff {
+            old,
+            new,
+            analysis,
+            output,
+        }) => run_diff(&old, &new, &analysis, &output),
+        #[cfg(feature = "serde")]
+        Some(Command::Check {
+            databases,
+            budget,
+            analysis,
+        }) => {
+            if !run_check(&databases, &load_budget(&budget)?, &analysis)? {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        None => {
+            let database = cli.database.expect("clap requires a database");
+            match cli.output.format_or_tui() {
+                Some(_) if cli.interface.any() => Cli::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "--target-size, --budget and --workload only apply to the interface, \
+                         not to a printed report",
+                    )
+                    .exit(),
+                Some(format) => {
+                    run_report(&database, format, &cli.analysis, &cli.overview, &cli.output)
+                }
+                None => run_tui(&database, &cli.analysis, &cli.overview, &cli.interface),
+            }
+        }
     }
+}
 
-    let db_path = &args[1];
+fn run_report(
+    db_path: &str,
+    format: OutputFormat,
+    analysis: &AnalysisArgs,
+    overview: &OverviewArgs,
+    output: &OutputArgs,
+) -> Result<()> {
+    let mut analyzer = analysis.open(db_path)?;
+    let mut report = DatabaseReport::generate(&mut analyzer)?;
+    #[cfg(feature = "serde")]
+    if let (true, Some(store)) = (overview.record, overview.history_store()) {
+        store
+            .append(&HistoryEntry::from_report(&report))
+            .with_context(|| format!("Cannot write {}", store.path().display()))?;
+    }
+    report
+        .tables
+        .sort_by(|a, b| overview.sort.compare(&a.table, &b.table));
+    output.write(render(&report, format, analysis.units))
+}
 
-    println!("Analyzing database: {}", db_path);
-    let tables = analyze_database(db_path)?;
+fn run_diff(
+    old_path: &str,
+    new_path: &str,
+    analysis: &AnalysisArgs,
+    output: &OutputArgs,
+) -> Result<()> {
+    let mut reports = Vec::new();
+    for db_path in [old_path, new_path] {
+        eprintln!("Analyzing {}...", db_path);
+        let mut analyzer = analysis.open(db_path)?;
+        reports.push(DatabaseReport::generate_sizes(&mut analyzer)?);
+    }
+    let diff = DatabaseDiff::between(&reports[0], &reports[1]);
 
-    if tables.is_empty() {
-        println!("No tables found in database.");
-        return Ok(());
+    match output.format_or_tui() {
+        Some(format) => output.write(render_diff(&diff, format, analysis.units)),
+        None => {
+            let mut app = App::diffing(diff);
+            app.units = analysis.units;
+            with_terminal(|terminal| run_diff_app(terminal, app))
+        }
     }
+}
+
+/// Prints every budget violation of every database; `false` when there were any.
+#[cfg(feature = "serde")]
+fn run_check(db_paths: &[String], budget: &Budget, args: &AnalysisArgs) -> Result<bool> {
+    let mut within = true;
+    for db_path in db_paths {
+        let mut analyzer = args.open(db_path)?;
+        let report = DatabaseReport::generate_sizes(&mut analyzer)?;
+        let tables: Vec<_> = report.tables.iter().map(|t| t.table.clone()).collect();
+        let violations = budget.check(&tables, report.space.file_bytes());
+        if violations.is_empty() {
+            println!("{}: within budget", db_path);
+            continue;
+        }
 
+        within = false;
+        println!("{}: {} over budget", db_path, violations.len());
+        for violation in &violations {
+            match violation.table() {
+                Some(table) => println!("  {}: {}", table, violation.describe(args.units)),
+                None => println!("  {}", violation.describe(args.units)),
+            }
+        }
+    }
+    Ok(within)
+}
+
+fn run_tui(
+    db_path: &str,
+    analysis: &AnalysisArgs,
+    overview: &OverviewArgs,
+    interface: &InterfaceArgs,
+) -> Result<()> {
+    // Fails before the TUI starts when the file is missing or not a database
+    let mut analyzer = analysis.open(db_path)?;
+
+    // Create app and run, with analysis filling it in from a worker thread
+    let mut app = App::analyzing(db_path.to_string());
+    app.sort = overview.sort;
+    app.units = analysis.units;
+    if !analyzer.has_dbstat() {
+        app.warnings.push(NO_DBSTAT_WARNING.to_string());
+    }
+    app.target_size = interface.target_size;
+    app.usage = interface.index_usage(&analyzer)?;
+    #[cfg(feature = "serde")]
+    {
+        app.budget = interface.budget()?;
+    }
+    #[cfg(feature = "serde")]
+    let store = overview.history_store();
+    #[cfg(feature = "serde")]
+    if let Some(store) = &store {
+        match store.load(db_path) {
+            Ok(history) => app.history = history,
+            Err(e) => app
+                .warnings
+                .push(format!("Cannot read {}: {}", store.path().display(), e)),
+        }
+    }
+
+    let analysis = spawn_analysis(analyzer.db_path().to_string(), analyzer.options());
+    with_terminal(|terminal| run_app(terminal, &mut app, analysis, &mut analyzer))?;
+
+    // Only a complete analysis is worth comparing later ones against
+    #[cfg(feature = "serde")]
+    if let (true, Some(store), AnalysisState::Done) = (overview.record, &store, &app.analysis) {
+        let indexes = app
+            .tables
+            .iter()
+            .map(|t| Ok((t.name.clone(), analyzer.indexes(&t.name)?.to_vec())))
+            .collect::<Result<_>>()?;
+        let entry = HistoryEntry {
+            recorded_at: app.analyzed_at,
+            database: database_key(db_path),
+            file_size: app.space.file_bytes(),
+            tables: app.tables,
+            indexes,
+        };
+        store
+            .append(&entry)
+            .with_context(|| format!("Cannot write {}", store.path().display()))?;
+    }
+    Ok(())
+}
+
+/// Runs `f` on the alternate screen, restoring the terminal afterwards.
+fn with_terminal<F>(f: F) -> Result<()>
+where
+    F: FnOnce(&mut Terminal<CrosstermBackend<Stdout>>) -> Result<()>,
+{
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -156,9 +711,7 @@ fn main() -> Result<()> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app and run
-    let app = App::new(db_path.to_string(), tables);
-    let res = run_app(&mut terminal, app);
+    let res = f(&mut terminal);
 
     // Restore terminal
     disable_raw_mode()?;
@@ -169,9 +722,6 @@ fn main() -> Result<()> {
     )?;
     terminal.show_cursor()?;
 
-    if let Err(err) = res {
-        eprintln!("Error: {:?}", err);
-    }
-
-    Ok(())
+    // Reported by main once the terminal is back to normal
+    res
 }
@@ -0,0 +1,722 @@
+//! Plain-text renderings of a [`DatabaseReport`] or a [`DatabaseDiff`] for
+//! scripts, cron jobs, CI and monitoring.
+
+use std::fmt::{Display, Write};
+use std::fs;
+use std::io::{self, Write as _};
+use std::path::Path;
+
+use crate::app::{IndexInfo, RowCount};
+use crate::diff::{Change, DatabaseDiff, DiffStatus, ObjectDiff};
+use crate::format::{format_number, format_row_count, format_signed, UnitStyle};
+use crate::report::{DatabaseReport, TableReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Aligned columns for reading in a terminal or a log.
+    Text,
+    #[cfg(feature = "serde")]
+    Json,
+    /// One row per table and per index.
+    Csv,
+    Markdown,
+    /// Metrics in the Prometheus text exposition format, e.g. for node_exporter's
+    /// textfile collector.
+    Prometheus,
+}
+
+impl OutputFormat {
+    /// Every format of this build, by the name used on the command line.
+    pub const ALL: &'static [(&'static str, OutputFormat)] = &[
+        ("text", OutputFormat::Text),
+        #[cfg(feature = "serde")]
+        ("json", OutputFormat::Json),
+        ("csv", OutputFormat::Csv),
+        ("markdown", OutputFormat::Markdown),
+        ("prometheus", OutputFormat::Prometheus),
+    ];
+}
+
+/// Renders `report` in `format`. Sizes in text and Markdown are written in
+/// `units`; CSV, JSON and Prometheus always carry exact byte counts.
+pub fn render(report: &DatabaseReport, format: OutputFormat, units: UnitStyle) -> String {
+    match format {
+        OutputFormat::Text => render_text(report, units),
+        #[cfg(feature = "serde")]
+        OutputFormat::Json => {
+            let mut json = report.to_json().expect("reports always serialize");
+            json.push('\n');
+            json
+        }
+        OutputFormat::Csv => render_csv(report),
+        OutputFormat::Markdown => render_markdown(report, units),
+        OutputFormat::Prometheus => render_prometheus(report),
+    }
+}
+
+/// Replaces `path` with `contents` in one step: readers such as the textfile
+/// collector see either the old file or the complete new one, never a partial write.
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?;
+    // Same directory, so the rename never crosses file systems; the textfile
+    // collector ignores names that do not end in .prom
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let result = fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn index_label(index: &IndexInfo) -> String {
+    let kind = match index.origin.constraint() {
+        Some(constraint) => format!("{} constraint", constraint),
+        None if index.is_unique => String::from("UNIQUE"),
+        None => String::from("INDEX"),
+    };
+    let mut label = format!("{} ({}) {}", index.name, index.columns, kind);
+    if let Some(partial) = &index.partial_clause {
+        label.push_str(&format!(" WHERE {}", partial));
+    }
+    label
+}
+
+fn row_count_text(table: &TableReport) -> String {
+    match table.table.row_count_kind {
+        RowCount::Pending | RowCount::Skipped => String::from("?"),
+        _ => format_row_count(&table.table),
+    }
+}
+
+fn render_text(report: &DatabaseReport, units: UnitStyle) -> String {
+    let space = &report.space;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "sqdu report for {}", report.database.path);
+    let _ = writeln!(
+        out,
+        "File {} ({} × {} pages) | Tables {} | Indexes {} | Internal {} | Freelist {} | Other {}",
+        units.format(space.file_bytes()),
+        format_number(space.page_count),
+        units.format(space.page_size),
+        units.format(space.table_bytes),
+        units.format(space.index_bytes),
+        units.format(space.internal_bytes),
+        units.format(space.freelist_bytes),
+        units.format(space.other_bytes()),
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "{:>10}  {:>6}  {:>12}  {:>3}  {:>10}  {:>6}  Name",
+        "Size", "%", "Rows", "Idx", "Idx Size", "Fill"
+    );
+
+    for table in &report.tables {
+        let info = &table.table;
+        let _ = writeln!(
+            out,
+            "{:>10}  {:>5.1}%  {:>12}  {:>3}  {:>10}  {:>5.1}%  {}",
+            units.format(info.size_bytes),
+            space.percentage(info.size_bytes),
+            row_count_text(table),
+            info.index_count,
+            units.format(info.index_size_bytes),
+            info.pages.efficiency() * 100.0,
+            info.name
+        );
+        if let Some(error) = &info.error {
+            let _ = writeln!(out, "{:>56}  ! {}", "", error);
+        }
+
+        for index in &table.indexes {
+            let _ = writeln!(
+                out,
+                "{:>10}  {:>5.1}%  {:>12}  {:>3}  {:>10}  {:>5.1}%    {}",
+                units.format(index.size_bytes),
+                space.percentage(index.size_bytes),
+                "",
+                "",
+                "",
+                index.pages.efficiency() * 100.0,
+                index_label(index)
+            );
+            if let Some(error) = &index.error {
+                let _ = writeln!(out, "{:>56}    ! {}", "", error);
+            }
+        }
+    }
+
+    if !report.findings.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "Findings");
+        for finding in &report.findings {
+            let _ = writeln!(out, "  {}: {}", finding.table(), finding.describe(units));
+        }
+    }
+
+    out
+}
+
+const CSV_HEADER: &[&str] = &[
+    "kind",
+    "table",
+    "name",
+    "size_bytes",
+    "row_count",
+    "row_count_kind",
+    "index_count",
+    "index_size_bytes",
+    "interior_pages",
+    "leaf_pages",
+    "overflow_pages",
+    "payload_bytes",
+    "unused_bytes",
+    "unique",
+    "columns",
+    "partial_clause",
+    "error",
+];
+
+fn render_csv(report: &DatabaseReport) -> String {
+    let mut out = String::new();
+    csv_row(&mut out, CSV_HEADER.iter().map(|s| s.to_string()));
+
+    for table in &report.tables {
+        let info = &table.table;
+        let row_count_kind = format!("{:?}", info.row_count_kind).to_lowercase();
+        csv_row(
+            &mut out,
+            [
+                "table".to_string(),
+                info.name.clone(),
+                info.name.clone(),
+                info.size_bytes.to_string(),
+                info.row_count.to_string(),
+                row_count_kind,
+                info.index_count.to_string(),
+                info.index_size_bytes.to_string(),
+                info.pages.interior_pages.to_string(),
+                info.pages.leaf_pages.to_string(),
+                info.pages.overflow_pages.to_string(),
+                info.pages.payload_bytes.to_string(),
+                info.pages.unused_bytes.to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+                info.error.clone().unwrap_or_default(),
+            ],
+        );
+
+        for index in &table.indexes {
+            csv_row(
+                &mut out,
+                [
+                    "index".to_string(),
+                    info.name.clone(),
+                    index.name.clone(),
+                    index.size_bytes.to_string(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    String::new(),
+                    index.pages.interior_pages.to_string(),
+                    index.pages.leaf_pages.to_string(),
+                    index.pages.overflow_pages.to_string(),
+                    index.pages.payload_bytes.to_string(),
+                    index.pages.unused_bytes.to_string(),
+                    index.is_unique.to_string(),
+                    index.columns.clone(),
+                    index.partial_clause.clone().unwrap_or_default(),
+                    index.error.clone().unwrap_or_default(),
+                ],
+            );
+        }
+    }
+
+    out
+}
+
+/// Appends one RFC 4180 record, quoting fields that need it.
+fn csv_row(out: &mut String, fields: impl IntoIterator<Item = String>) {
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|f| {
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f
+            }
+        })
+        .collect();
+    out.push_str(&fields.join(","));
+    out.push_str("\r\n");
+}
+
+fn render_markdown(report: &DatabaseReport, units: UnitStyle) -> String {
+    let space = &report.space;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# sqdu report for `{}`", report.database.path);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "| | Size | % |");
+    let _ = writeln!(out, "|---|---:|---:|");
+    for (name, bytes) in [
+        ("File", space.file_bytes()),
+        ("Tables", space.table_bytes),
+        ("Indexes", space.index_bytes),
+        ("Internal", space.internal_bytes),
+        ("Freelist", space.freelist_bytes),
+        ("Other", space.other_bytes()),
+    ] {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {:.1}% |",
+            name,
+            units.format(bytes),
+            space.percentage(bytes)
+        );
+    }
+    let _ = writeln!(out);
+
+    let _ = writeln!(
+        out,
+        "| Name | Size | % | Rows | Indexes | Index size | Fill |"
+    );
+    let _ = writeln!(out, "|---|---:|---:|---:|---:|---:|---:|");
+    for table in &report.tables {
+        let info = &table.table;
+        let mut name = markdown_escape(&info.name);
+        if let Some(error) = &info.error {
+            name.push_str(&format!(" ⚠ {}", markdown_escape(error)));
+        }
+        let _ = writeln!(
+            out,
+            "| **{}** | {} | {:.1}% | {} | {} | {} | {:.1}% |",
+            name,
+            units.format(info.size_bytes),
+            space.percentage(info.size_bytes),
+            row_count_text(table),
+            info.index_count,
+            units.format(info.index_size_bytes),
+            info.pages.efficiency() * 100.0
+        );
+
+        for index in &table.indexes {
+            let _ = writeln!(
+                out,
+                "| ↳ {} | {} | {:.1}% | | | | {:.1}% |",
+                markdown_escape(&index_label(index)),
+                units.format(index.size_bytes),
+                space.percentage(index.size_bytes),
+                index.pages.efficiency() * 100.0
+            );
+        }
+    }
+
+    if !report.findings.is_empty() {
+        let _ = writeln!(out);
+        let _ = writeln!(out, "## Findings");
+        let _ = writeln!(out);
+        for finding in &report.findings {
+            let _ = writeln!(
+                out,
+                "- **{}**: {}",
+                markdown_escape(finding.table()),
+                markdown_escape(&finding.describe(units))
+            );
+        }
+    }
+
+    out
+}
+
+fn markdown_escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Renders `diff` in `format`, like [`render`] does for a single report.
+pub fn render_diff(diff: &DatabaseDiff, format: OutputFormat, units: UnitStyle) -> String {
+    match format {
+        OutputFormat::Text => render_diff_text(diff, units),
+        #[cfg(feature = "serde")]
+        OutputFormat::Json => {
+            let mut json = diff.to_json().expect("diffs always serialize");
+            json.push('\n');
+            json
+        }
+        OutputFormat::Csv => render_diff_csv(diff),
+        OutputFormat::Markdown => render_diff_markdown(diff, units),
+        OutputFormat::Prometheus => render_diff_prometheus(diff),
+    }
+}
+
+/// `old -> new (+delta, +pct%)` for a summary line.
+pub fn change_summary(change: &Change, units: UnitStyle) -> String {
+    let mut text = format!(
+        "{} -> {} ({}",
+        units.format(change.old.unwrap_or(0)),
+        units.format(change.new.unwrap_or(0)),
+        units.format_delta(change.delta())
+    );
+    if let Some(percent) = change.percent() {
+        text.push_str(&format!(", {:+.1}%", percent));
+    }
+    text.push(')');
+    text
+}
+
+fn size_or_dash(size: Option<u64>, units: UnitStyle) -> String {
+    size.map_or_else(|| String::from("-"), |s| units.format(s))
+}
+
+fn rows_or_dash(rows: Option<u64>) -> String {
+    rows.map_or_else(|| String::from("-"), format_number)
+}
+
+fn percent_text(change: &Change) -> String {
+    change
+        .percent()
+        .map_or_else(String::new, |p| format!("{:+.1}%", p))
+}
+
+/// Columns shared by every row of a diff listing, up to the name.
+pub fn diff_columns(object: &ObjectDiff, units: UnitStyle) -> String {
+    let (old_rows, new_rows, row_change) = match &object.rows {
+        Some(rows) if rows.old.is_some() || rows.new.is_some() => (
+            rows_or_dash(rows.old),
+            rows_or_dash(rows.new),
+            format_signed(rows.delta()),
+        ),
+        _ => (String::new(), String::new(), String::new()),
+    };
+    format!(
+        "{:>10}  {:>10}  {:>11}  {:>7}  {:>12}  {:>12}  {:>11}",
+        size_or_dash(object.size.old, units),
+        size_or_dash(object.size.new, units),
+        units.format_delta(object.size.delta()),
+        percent_text(&object.size),
+        old_rows,
+        new_rows,
+        row_change
+    )
+}
+
+/// Column titles matching [`diff_columns`].
+pub fn diff_header() -> String {
+    format!(
+        "{:>10}  {:>10}  {:>11}  {:>7}  {:>12}  {:>12}  {:>11}  Name",
+        "Old", "New", "Change", "%", "Old rows", "New rows", "Row change"
+    )
+}
+
+/// ` (new)` or ` (dropped)` after the name of an object that is only on one side.
+pub fn status_suffix(status: DiffStatus) -> &'static str {
+    match status {
+        DiffStatus::Added => " (new)",
+        DiffStatus::Dropped => " (dropped)",
+        DiffStatus::Changed | DiffStatus::Unchanged => "",
+    }
+}
+
+fn render_diff_text(diff: &DatabaseDiff, units: UnitStyle) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "sqdu diff {} -> {}", diff.old.path, diff.new.path);
+    let _ = writeln!(
+        out,
+        "File {} | Freelist {}",
+        change_summary(&diff.file, units),
+        change_summary(&diff.freelist, units)
+    );
+    let _ = writeln!(out);
+    let _ = writeln!(out, "{}", diff_header());
+
+    for table in &diff.tables {
+        let _ = writeln!(
+            out,
+            "{}  {}{}",
+            diff_columns(&table.table, units),
+            table.table.name,
+            status_suffix(table.table.status)
+        );
+        for index in &table.indexes {
+            let _ = writeln!(
+                out,
+                "{}    {}{}",
+                diff_columns(index, units),
+                index.name,
+                status_suffix(index.status)
+            );
+        }
+    }
+
+    out
+}
+
+const DIFF_CSV_HEADER: &[&str] = &[
+    "kind",
+    "table",
+    "name",
+    "status",
+    "old_size_bytes",
+    "new_size_bytes",
+    "size_change",
+    "size_change_percent",
+    "old_row_count",
+    "new_row_count",
+    "row_count_change",
+];
+
+fn render_diff_csv(diff: &DatabaseDiff) -> String {
+    let mut out = String::new();
+    csv_row(&mut out, DIFF_CSV_HEADER.iter().map(|s| s.to_string()));
+
+    let optional = |n: Option<u64>| n.map_or_else(String::new, |n| n.to_string());
+    let record = |kind: &str, table: &str, object: &ObjectDiff| {
+        let rows = object.rows.unwrap_or_default();
+        [
+            kind.to_string(),
+            table.to_string(),
+            object.name.clone(),
+            format!("{:?}", object.status).to_lowercase(),
+            optional(object.size.old),
+            optional(object.size.new),
+            object.size.delta().to_string(),
+            object
+                .size
+                .percent()
+                .map_or_else(String::new, |p| format!("{:.2}", p)),
+            optional(rows.old),
+            optional(rows.new),
+            if object.rows.is_some() {
+                rows.delta().to_string()
+            } else {
+                String::new()
+            },
+        ]
+    };
+
+    for table in &diff.tables {
+        csv_row(&mut out, record("table", &table.table.name, &table.table));
+        for index in &table.indexes {
+            csv_row(&mut out, record("index", &table.table.name, index));
+        }
+    }
+
+    out
+}
+
+fn render_diff_markdown(diff: &DatabaseDiff, units: UnitStyle) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# sqdu diff `{}` → `{}`", diff.old.path, diff.new.path);
+    let _ = writeln!(out);
+    let _ = writeln!(out, "- File: {}", change_summary(&diff.file, units));
+    let _ = writeln!(out, "- Freelist: {}", change_summary(&diff.freelist, units));
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "| Name | Old | New | Change | % | Old rows | New rows | Row change |"
+    );
+    let _ = writeln!(out, "|---|---:|---:|---:|---:|---:|---:|---:|");
+
+    let row = |out: &mut String, name: String, object: &ObjectDiff| {
+        let rows = object.rows.unwrap_or_default();
+        let (old_rows, new_rows, row_change) = if object.rows.is_some() {
+            (
+                rows_or_dash(rows.old),
+                rows_or_dash(rows.new),
+                format_signed(rows.delta()),
+            )
+        } else {
+            (String::new(), String::new(), String::new())
+        };
+        let _ = writeln!(
+            out,
+            "| {}{} | {} | {} | {} | {} | {} | {} | {} |",
+            name,
+            status_suffix(object.status),
+            size_or_dash(object.size.old, units),
+            size_or_dash(object.size.new, units),
+            units.format_delta(object.size.delta()),
+            percent_text(&object.size),
+            old_rows,
+            new_rows,
+            row_change
+        );
+    };
+
+    for table in &diff.tables {
+        row(
+            &mut out,
+            format!("**{}**", markdown_escape(&table.table.name)),
+            &table.table,
+        );
+        for index in &table.indexes {
+            row(
+                &mut out,
+                format!("↳ {}", markdown_escape(&index.name)),
+                index,
+            );
+        }
+    }
+
+    out
+}
+
+/// `{name="value",...}` with the escapes the exposition format requires.
+fn prometheus_labels(labels: &[(&str, &str)]) -> String {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+/// Appends one gauge with its samples, each a label set and a value.
+fn prometheus_gauge<V: Display>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (String, V)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{} {}", name, labels, value);
+    }
+}
+
+fn render_prometheus(report: &DatabaseReport) -> String {
+    let db = report.database.path.as_str();
+    let database = prometheus_labels(&[("database", db)]);
+    let mut out = String::new();
+
+    prometheus_gauge(
+        &mut out,
+        "sqdu_file_size_bytes",
+        "Size of the database file.",
+        [(database.clone(), report.space.file_bytes())],
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_freelist_bytes",
+        "Bytes in unused pages on the freelist.",
+        [(database.clone(), report.space.freelist_bytes)],
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_freelist_pages",
+        "Unused pages on the freelist.",
+        [(database.clone(), report.database.freelist_count)],
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_table_size_bytes",
+        "Size of a table's b-tree, without its indexes.",
+        report.tables.iter().map(|t| {
+            let labels = prometheus_labels(&[("database", db), ("table", &t.table.name)]);
+            (labels, t.table.size_bytes)
+        }),
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_table_rows",
+        "Rows in a table, exact or estimated; left out when not counted.",
+        report.tables.iter().filter_map(|t| {
+            let labels = prometheus_labels(&[("database", db), ("table", &t.table.name)]);
+            Some((labels, t.table.known_row_count()?))
+        }),
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_index_size_bytes",
+        "Size of an index's b-tree.",
+        report.tables.iter().flat_map(|t| {
+            t.indexes.iter().map(|index| {
+                let labels = prometheus_labels(&[
+                    ("database", db),
+                    ("table", &t.table.name),
+                    ("index", &index.name),
+                ]);
+                (labels, index.size_bytes)
+            })
+        }),
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_analysis_timestamp_seconds",
+        "When the analysis ran, in seconds since the Unix epoch.",
+        [(database, report.generated_at)],
+    );
+
+    out
+}
+
+fn render_diff_prometheus(diff: &DatabaseDiff) -> String {
+    let (old, new) = (diff.old.path.as_str(), diff.new.path.as_str());
+    let database = prometheus_labels(&[("database", new), ("baseline", old)]);
+    let mut out = String::new();
+
+    prometheus_gauge(
+        &mut out,
+        "sqdu_file_size_change_bytes",
+        "Growth of the database file since the baseline.",
+        [(database.clone(), diff.file.delta())],
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_freelist_change_bytes",
+        "Growth of the freelist since the baseline.",
+        [(database, diff.freelist.delta())],
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_table_size_change_bytes",
+        "Growth of a table's b-tree since the baseline.",
+        diff.tables.iter().map(|t| {
+            let labels = prometheus_labels(&[
+                ("database", new),
+                ("baseline", old),
+                ("table", &t.table.name),
+            ]);
+            (labels, t.table.size.delta())
+        }),
+    );
+    prometheus_gauge(
+        &mut out,
+        "sqdu_index_size_change_bytes",
+        "Growth of an index's b-tree since the baseline.",
+        diff.tables.iter().flat_map(|t| {
+            t.indexes.iter().map(|index| {
+                let labels = prometheus_labels(&[
+                    ("database", new),
+                    ("baseline", old),
+                    ("table", &t.table.name),
+                    ("index", &index.name),
+                ]);
+                (labels, index.size.delta())
+            })
+        }),
+    );
+
+    out
+}
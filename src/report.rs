@@ -0,0 +1,169 @@
+//! A self-contained, serializable record of one analysis.
+//!
+//! [`DatabaseReport`] bundles database metadata, the space summary and every table
+//! with its indexes and schema details. With the `serde` feature it converts to and
+//! from JSON; `schema_version` is bumped whenever a field changes meaning or goes
+//! away, so stored reports can be told apart from ones a newer sqdu wrote.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::analyzer::Analyzer;
+use crate::app::{IndexInfo, SpaceSummary, TableDetails, TableInfo};
+use crate::error::Result;
+use crate::findings::{sort_findings, Finding};
+
+/// Version of the report layout produced by this build.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DatabaseReport {
+    pub schema_version: u32,
+    /// Name and version of the tool that wrote the report.
+    pub generated_by: String,
+    /// Seconds since the Unix epoch.
+    pub generated_at: u64,
+    pub database: DatabaseMetadata,
+    pub space: SpaceSummary,
+    /// Largest first.
+    pub tables: Vec<TableReport>,
+    /// The most bytes to reclaim first; missing from reports of older versions.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub findings: Vec<Finding>,
+}
+
+/// Facts about the database file itself.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DatabaseMetadata {
+    pub path: String,
+    pub file_size: u64,
+    pub page_size: u64,
+    pub page_count: u64,
+    pub freelist_count: u64,
+    pub encoding: String,
+    pub journal_mode: String,
+    /// 0 = none, 1 = full, 2 = incremental.
+    pub auto_vacuum: u32,
+    pub user_version: i64,
+    pub application_id: i64,
+    pub sqlite_version: String,
+    /// Sizes came from `dbstat` rather than sqdu's own file reader.
+    pub dbstat: bool,
+    /// The numbers describe a snapshot copy taken at `generated_at`.
+    pub snapshot: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TableReport {
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    pub table: TableInfo,
+    pub indexes: Vec<IndexInfo>,
+    pub details: TableDetails,
+}
+
+impl DatabaseReport {
+    /// Runs every analysis on `analyzer` and collects the results. A table whose
+    /// findings cannot be worked out keeps its sizes and records the error.
+    pub fn generate(analyzer: &mut Analyzer) -> Result<Self> {
+        let mut report = Self::generate_sizes(analyzer)?;
+        for table in &mut report.tables {
+            match analyzer.table_findings(&table.table.name) {
+                Ok(findings) => report.findings.extend(findings),
+                Err(e) => {
+                    let error = format!("findings: {}", e);
+                    table.table.error = Some(match table.table.error.take() {
+                        Some(previous) => format!("{}; {}", previous, error),
+                        None => error,
+                    });
+                }
+            }
+        }
+        sort_findings(&mut report.findings);
+        Ok(report)
+    }
+
+    /// Like [`generate`](Self::generate) but without findings, for comparing
+    /// sizes.
+    pub fn generate_sizes(analyzer: &mut Analyzer) -> Result<Self> {
+        let database = metadata(analyzer)?;
+        let space = analyzer.space()?.clone();
+
+        let mut tables = Vec::new();
+        for table in analyzer.tables()?.to_vec() {
+            let indexes = analyzer.indexes(&table.name)?.to_vec();
+            let details = analyzer.table_details(&table.name)?.clone();
+            tables.push(TableReport {
+                table,
+                indexes,
+                details,
+            });
+        }
+
+        Ok(Self {
+            schema_version: SCHEMA_VERSION,
+            generated_by: format!("sqdu {}", env!("CARGO_PKG_VERSION")),
+            generated_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            database,
+            space,
+            tables,
+            findings: Vec::new(),
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DatabaseReport {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Parses a stored report, refusing ones written with a newer schema.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let report: Self = serde_json::from_str(json)?;
+        if report.schema_version > SCHEMA_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "report schema version {} is newer than the supported version {}",
+                report.schema_version, SCHEMA_VERSION
+            )));
+        }
+        Ok(report)
+    }
+}
+
+fn metadata(analyzer: &Analyzer) -> Result<DatabaseMetadata> {
+    let conn = analyzer.connection();
+    let pragma_u64 = |name: &str| -> Result<u64> {
+        Ok(conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))?)
+    };
+    let pragma_i64 = |name: &str| -> Result<i64> {
+        Ok(conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))?)
+    };
+    let pragma_text = |name: &str| -> Result<String> {
+        Ok(conn.query_row(&format!("PRAGMA {}", name), [], |row| row.get(0))?)
+    };
+
+    let page_size = pragma_u64("page_size")?;
+    let page_count = pragma_u64("page_count")?;
+    let file_size =
+        std::fs::metadata(analyzer.source_path()).map_or(page_size * page_count, |m| m.len());
+
+    Ok(DatabaseMetadata {
+        path: analyzer.source_path().to_string(),
+        file_size,
+        page_size,
+        page_count,
+        freelist_count: pragma_u64("freelist_count")?,
+        encoding: pragma_text("encoding")?,
+        journal_mode: analyzer.journal_mode()?,
+        auto_vacuum: pragma_u64("auto_vacuum")? as u32,
+        user_version: pragma_i64("user_version")?,
+        application_id: pragma_i64("application_id")?,
+        sqlite_version: conn.query_row("SELECT sqlite_version()", [], |row| row.get(0))?,
+        dbstat: analyzer.has_dbstat(),
+        snapshot: analyzer.is_snapshot(),
+    })
+}
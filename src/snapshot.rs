@@ -0,0 +1,109 @@
+//! Point-in-time copies of a database for analysis of live files.
+//!
+//! Every view of sqdu opens its own connection, so while an application keeps
+//! writing, the table overview, index list and schema details could each see a
+//! different state of the file. A [`Snapshot`] copies the database once with
+//! SQLite's online backup API, inside a single read transaction, into a private
+//! temporary file; the whole session then runs against that copy. Pages are copied
+//! one to one, so sizes and page counts match the original, and changes still in
+//! a WAL file are folded in.
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::Connection;
+
+use crate::app::{open_database, OpenOptions};
+use crate::error::{Result, SqduError};
+
+/// A temporary copy of a database, deleted again when dropped.
+pub struct Snapshot {
+    path: String,
+    /// Of the original; the copy is always in rollback journal mode.
+    journal_mode: String,
+}
+
+impl Snapshot {
+    /// Copies `db_path` into a new file in the system temp directory.
+    ///
+    /// Waits up to `options.busy_timeout` for a writer to release its lock.
+    pub fn create(db_path: &str, options: OpenOptions) -> Result<Self> {
+        let source = open_database(db_path, options)?;
+        let journal_mode = source.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        // Removes the partial copy should the backup fail
+        let snapshot = Self {
+            path: create_private_file()?,
+            journal_mode,
+        };
+
+        // Scoped so the connection to the copy is closed before the copy is
+        // analyzed or removed
+        {
+            let mut target = Connection::open(&snapshot.path)?;
+            let backup = Backup::new(&source, &mut target)?;
+
+            // Copying every page in one step keeps the read transaction open
+            // throughout, so the copy is consistent
+            match backup.step(-1)? {
+                StepResult::Done => {}
+                // A writer kept its lock past the busy timeout
+                _ => return Err(SqduError::Busy),
+            }
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Where the copy lives, for the `db_path` arguments of the analysis functions.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The journal mode of the database that was copied.
+    pub fn journal_mode(&self) -> &str {
+        &self.journal_mode
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        // Left behind by a crash, or by a connection that was not closed cleanly
+        for suffix in ["-journal", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", self.path, suffix));
+        }
+    }
+}
+
+/// Creates a new, empty file in the system temp directory that only this user
+/// can read, without following a file or link someone else put there first.
+fn create_private_file() -> Result<String> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = std::env::temp_dir();
+    loop {
+        let path = dir.join(format!(
+            "sqdu-snapshot-{}-{}.db",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let Some(path_str) = path.to_str().map(String::from) else {
+            return Err(SqduError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("temp directory {} is not valid UTF-8", dir.display()),
+            )));
+        };
+
+        let mut file = std::fs::OpenOptions::new();
+        file.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut file, 0o600);
+        match file.open(&path) {
+            Ok(_) => return Ok(path_str),
+            // Taken by an earlier run that had the same process id; try the next name
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(SqduError::Io(e)),
+        }
+    }
+}
@@ -2,7 +2,7 @@ use ratatui::{
     layout::{Constraint, Layout},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
     Frame,
 };
 use syntect::{
@@ -12,40 +12,12 @@ use syntect::{
     util::LinesWithEndings,
 };
 
-use crate::app::{App, ViewMode};
-
-pub fn format_number(n: u64) -> String {
-    let s = n.to_string();
-    let mut result = String::new();
-    let mut count = 0;
-
-    for c in s.chars().rev() {
-        if count > 0 && count % 3 == 0 {
-            result.push(',');
-        }
-        result.push(c);
-        count += 1;
-    }
-
-    result.chars().rev().collect()
-}
-
-pub fn format_bytes(bytes: u64) -> String {
-    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
-    let mut size = bytes as f64;
-    let mut unit_index = 0;
-
-    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size /= 1024.0;
-        unit_index += 1;
-    }
-
-    if unit_index == 0 {
-        format!("{} {}", bytes, UNITS[0])
-    } else {
-        format!("{:.1} {}", size, UNITS[unit_index])
-    }
-}
+use crate::app::{AnalysisState, App, SpaceSummary, TableInfo, ViewMode};
+use crate::diff::{DatabaseDiff, DiffStatus, ObjectDiff};
+use crate::format::{format_number, format_row_count, UnitStyle};
+use crate::history::format_date;
+use crate::output::{change_summary, diff_columns, diff_header, status_suffix};
+use crate::workload::IndexUsage;
 
 fn syntect_to_ratatui_color(c: syntect::highlighting::Color) -> Color {
     Color::Rgb(c.r, c.g, c.b)
@@ -106,11 +78,71 @@ fn format_sql(sql: &str) -> String {
     result
 }
 
+fn space_summary_line(space: &SpaceSummary, units: UnitStyle) -> Line<'static> {
+    let label = Style::default().fg(Color::Yellow);
+    let part = |name: &str, bytes: u64| {
+        vec![
+            Span::styled(format!("{} ", name), label),
+            Span::raw(format!(
+                "{} ({:.1}%)",
+                units.format(bytes),
+                space.percentage(bytes)
+            )),
+            Span::styled("  │  ", Style::default().fg(Color::DarkGray)),
+        ]
+    };
+
+    let mut spans = vec![
+        Span::styled("File ", label),
+        Span::raw(format!(
+            "{} ({} × {} pages)",
+            units.format(space.file_bytes()),
+            format_number(space.page_count),
+            units.format(space.page_size)
+        )),
+        Span::styled("  │  ", Style::default().fg(Color::DarkGray)),
+    ];
+    spans.extend(part("Tables", space.table_bytes));
+    spans.extend(part("Indexes", space.index_bytes));
+    spans.extend(part("Internal", space.internal_bytes));
+    spans.extend(part("Freelist", space.freelist_bytes));
+    spans.extend(part("Other", space.other_bytes()));
+    spans.pop();
+
+    Line::from(spans)
+}
+
 pub fn ui(f: &mut Frame, app: &mut App) {
+    let units = app.units;
+
+    // The space summary panel is only shown on the table overview and the diff
+    let summary_height = match app.view_mode {
+        ViewMode::Tables | ViewMode::Diff => 3,
+        _ => 0,
+    };
+
+    let banner_height = if app.warnings.is_empty() {
+        0
+    } else {
+        app.warnings.len() as u16 + 2
+    };
+
+    let progress_height = if app.analysis.is_running() { 3 } else { 0 };
+
+    // Growth of the selected table, once there is recorded history to compare with
+    let history_height = match app.view_mode {
+        ViewMode::Tables if !app.history.is_empty() => 3,
+        _ => 0,
+    };
+
     let chunks = Layout::default()
         .constraints([
             Constraint::Length(3),
+            Constraint::Length(banner_height),
+            Constraint::Length(summary_height),
+            Constraint::Length(progress_height),
             Constraint::Min(0),
+            Constraint::Length(history_height),
             Constraint::Length(3),
         ])
         .split(f.area());
@@ -124,6 +156,11 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         ViewMode::TableInfo(table_name) => {
             format!("sqdu - Table Info: {} - {}", table_name, app.db_path)
         }
+        ViewMode::Diff => match &app.diff {
+            Some(diff) => format!("sqdu - Diff: {} -> {}", diff.old.path, diff.new.path),
+            None => String::from("sqdu - Diff"),
+        },
+        ViewMode::Findings => format!("sqdu - Findings - {}", app.db_path),
     };
 
     let header = Paragraph::new(header_text)
@@ -131,37 +168,134 @@ pub fn ui(f: &mut Frame, app: &mut App) {
         .style(Style::default().fg(Color::Cyan));
     f.render_widget(header, chunks[0]);
 
+    if banner_height > 0 {
+        let lines: Vec<Line> = app
+            .warnings
+            .iter()
+            .map(|w| Line::from(format!("⚠ {}", w)))
+            .collect();
+        let banner = Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Red))
+                    .title("Warning"),
+            )
+            .style(Style::default().fg(Color::Yellow));
+        f.render_widget(banner, chunks[1]);
+    }
+
+    if summary_height > 0 {
+        let line = match &app.diff {
+            Some(diff) => diff_summary_line(diff, units),
+            None => space_summary_line(&app.space, units),
+        };
+        // The file as a whole can be over budget too
+        let title = match app.budget.as_ref().and_then(|b| b.max_file_size) {
+            Some(max) if app.diff.is_none() && app.total_size > max => {
+                format!("Space (over budget: {} allowed)", units.format(max))
+            }
+            _ => String::from("Space"),
+        };
+        let summary =
+            Paragraph::new(line).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(summary, chunks[2]);
+    }
+
+    if progress_height > 0 {
+        let (ratio, label) = match &app.analysis {
+            AnalysisState::Counting {
+                counted,
+                total,
+                current,
+            } => {
+                let ratio = if *total > 0 {
+                    *counted as f64 / *total as f64
+                } else {
+                    1.0
+                };
+                let label = if current.is_empty() {
+                    format!("Counting rows {}/{}", counted, total)
+                } else {
+                    format!(
+                        "Counting rows {}/{}: {}",
+                        counted,
+                        total,
+                        current.join(", ")
+                    )
+                };
+                (ratio, label)
+            }
+            _ => (0.0, String::from("Measuring pages...")),
+        };
+        let gauge = Gauge::default()
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Analyzing (s: skip running counts | Esc: cancel)"),
+            )
+            .gauge_style(Style::default().fg(Color::Cyan).bg(Color::Black))
+            .ratio(ratio)
+            .label(label);
+        f.render_widget(gauge, chunks[3]);
+    }
+
     // Main content area - either tables or indexes
     match &app.view_mode {
         ViewMode::Tables => {
             // Add header row explaining columns
             let mut all_items = vec![
-                ListItem::new("     Size      %          Rows  Idx   Idx Size  Table Name")
+                ListItem::new("     Size      %          Rows  Idx   Idx Size   Interior       Leaf   Overflow   Fill  Table Name")
                     .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-                ListItem::new("─────────────────────────────────────────────────────────────────────────────────")
+                ListItem::new("──────────────────────────────────────────────────────────────────────────────────────────────────────────────")
                     .style(Style::default().fg(Color::DarkGray)),
             ];
 
+            if !app.history.is_empty() {
+                all_items[0] = ListItem::new("     Size      %          Rows  Idx   Idx Size   Interior       Leaf   Overflow   Fill  History       Table Name")
+                    .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+            }
+
             let table_items: Vec<ListItem> = app
                 .tables
                 .iter()
                 .map(|table| {
-                    let percentage = if app.total_size > 0 {
-                        (table.size_bytes as f64 / app.total_size as f64) * 100.0
+                    let percentage = app.space.percentage(table.size_bytes);
+
+                    let error_marker = if table.error.is_some() { " [ERROR]" } else { "" };
+                    let over_budget = app
+                        .budget
+                        .as_ref()
+                        .is_some_and(|b| !b.check_table(table).is_empty());
+                    let budget_marker = if over_budget { " [OVER BUDGET]" } else { "" };
+                    let sparkline = if app.history.is_empty() {
+                        String::new()
                     } else {
-                        0.0
+                        format!("{:<12}  ", app.table_series(table).sparkline(12))
                     };
-
                     let content = format!(
-                        "{:>9}  {:>5.1}%  {:>10} rows  {:>2} idx  {:>9} idx size  {}",
-                        format_bytes(table.size_bytes),
+                        "{:>9}  {:>5.1}%  {:>10} rows  {:>2} idx  {:>9} idx size  {:>9}  {:>9}  {:>9}  {:>5.1}%  {}{}{}{}",
+                        units.format(table.size_bytes),
                         percentage,
-                        format_number(table.row_count),
+                        format_row_count(table),
                         table.index_count,
-                        format_bytes(table.index_size_bytes),
-                        table.name
+                        units.format(table.index_size_bytes),
+                        units.format(table.pages.interior_bytes),
+                        units.format(table.pages.leaf_bytes),
+                        units.format(table.pages.overflow_bytes),
+                        table.pages.efficiency() * 100.0,
+                        sparkline,
+                        table.name,
+                        error_marker,
+                        budget_marker
                     );
-                    ListItem::new(content)
+                    let item = ListItem::new(content);
+                    let item = if over_budget {
+                        item.style(Style::default().fg(Color::Magenta))
+                    } else {
+                        item
+                    };
+                    error_style(item, &table.error)
                 })
                 .collect();
 
@@ -176,14 +310,14 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                 )
                 .highlight_symbol(">> ");
 
-            f.render_stateful_widget(list, chunks[1], &mut app.list_state);
+            f.render_stateful_widget(list, chunks[4], &mut app.list_state);
         }
         ViewMode::Indexes(table_name) => {
             // Add header row explaining columns
             let mut all_items = vec![
-                ListItem::new("    Size      Type    Columns                                   Name")
+                ListItem::new("    Size   Interior       Leaf   Overflow   Fill  Type    Columns                                   Name")
                     .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
-                ListItem::new("─────────────────────────────────────────────────────────────────────────────────")
+                ListItem::new("──────────────────────────────────────────────────────────────────────────────────────────────────────────────")
                     .style(Style::default().fg(Color::DarkGray)),
             ];
 
@@ -192,20 +326,49 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                 .iter()
                 .map(|index| {
                     let type_marker = if index.is_unique { "UNIQUE" } else { "INDEX " };
-                    let partial_marker = if index.partial_clause.is_some() {
-                        " [PARTIAL]"
+                    let partial_marker = if index.is_partial { " [PARTIAL]" } else { "" };
+                    let error_marker = if index.error.is_some() {
+                        " [ERROR]"
                     } else {
                         ""
                     };
+                    let constraint_marker = index
+                        .origin
+                        .constraint()
+                        .map(|c| format!(" [{}]", c))
+                        .unwrap_or_default();
+                    let redundant = app.redundant.iter().any(|r| r.index == index.name);
+                    let redundant_marker = if redundant { " [REDUNDANT]" } else { "" };
+                    let hits = app.usage.as_ref().map(|u| u.hits(&index.name));
+                    let usage_marker = match hits {
+                        None => String::new(),
+                        Some(0) => String::from(" [NEVER USED]"),
+                        Some(1) => String::from(" [1 hit]"),
+                        Some(n) => format!(" [{} hits]", format_number(n)),
+                    };
                     let content = format!(
-                        "{:>9}  {}  {:<40}  {}{}",
-                        format_bytes(index.size_bytes),
+                        "{:>9}  {:>9}  {:>9}  {:>9}  {:>5.1}%  {}  {:<40}  {}{}{}{}{}{}",
+                        units.format(index.size_bytes),
+                        units.format(index.pages.interior_bytes),
+                        units.format(index.pages.leaf_bytes),
+                        units.format(index.pages.overflow_bytes),
+                        index.pages.efficiency() * 100.0,
                         type_marker,
                         index.columns,
                         index.name,
-                        partial_marker
+                        constraint_marker,
+                        partial_marker,
+                        redundant_marker,
+                        usage_marker,
+                        error_marker
                     );
-                    ListItem::new(content)
+                    let item = ListItem::new(content);
+                    let item = if redundant || hits == Some(0) {
+                        item.style(Style::default().fg(Color::Yellow))
+                    } else {
+                        item
+                    };
+                    error_style(item, &index.error)
                 })
                 .collect();
 
@@ -215,8 +378,72 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                 .block(
                     Block::default()
                         .borders(Borders::ALL)
-                        .title(format!("Indexes for {}", table_name)),
+                        .title(indexes_title(table_name, app.usage.as_ref())),
+                )
+                .highlight_style(
+                    Style::default()
+                        .bg(Color::DarkGray)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .highlight_symbol(">> ");
+
+            f.render_stateful_widget(list, chunks[4], &mut app.list_state);
+        }
+        ViewMode::Diff => {
+            let mut all_items = vec![
+                ListItem::new(format!("   {}", diff_header()))
+                    .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                ListItem::new("──────────────────────────────────────────────────────────────────────────────────────────────────────────────")
+                    .style(Style::default().fg(Color::DarkGray)),
+            ];
+
+            if let Some(diff) = &app.diff {
+                for table in &diff.tables {
+                    all_items.push(diff_item(&table.table, "", units));
+                    for index in &table.indexes {
+                        all_items.push(diff_item(index, "  ↳ ", units));
+                    }
+                }
+            }
+
+            let list = List::new(all_items)
+                .block(Block::default().borders(Borders::ALL).title("Changes"))
+                .highlight_style(
+                    Style::default()
+                        .bg(Color::DarkGray)
+                        .add_modifier(Modifier::BOLD),
                 )
+                .highlight_symbol(">> ");
+
+            f.render_stateful_widget(list, chunks[4], &mut app.list_state);
+        }
+        ViewMode::Findings => {
+            let mut all_items = vec![
+                ListItem::new("     Reclaims  Table                 Finding")
+                    .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+                ListItem::new("──────────────────────────────────────────────────────────────────────────────────────────────────────────────")
+                    .style(Style::default().fg(Color::DarkGray)),
+            ];
+            if app.findings.is_empty() {
+                all_items.push(
+                    ListItem::new("Nothing found").style(Style::default().fg(Color::DarkGray)),
+                );
+            }
+            all_items.extend(app.findings.iter().map(|finding| {
+                ListItem::new(format!(
+                    "{:>10}  {:<20}  {}",
+                    units.format(finding.reclaimable_bytes()),
+                    finding.table(),
+                    finding.describe(units)
+                ))
+            }));
+
+            let reclaimable: u64 = app.findings.iter().map(|f| f.reclaimable_bytes()).sum();
+            let list = List::new(all_items)
+                .block(Block::default().borders(Borders::ALL).title(format!(
+                    "Findings ({} reclaimable)",
+                    units.format(reclaimable)
+                )))
                 .highlight_style(
                     Style::default()
                         .bg(Color::DarkGray)
@@ -224,7 +451,7 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                 )
                 .highlight_symbol(">> ");
 
-            f.render_stateful_widget(list, chunks[1], &mut app.list_state);
+            f.render_stateful_widget(list, chunks[4], &mut app.list_state);
         }
         ViewMode::TableInfo(table_name) => {
             if let Some(details) = &app.table_details {
@@ -281,10 +508,25 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                     )));
 
                     for fk in &details.foreign_keys {
-                        all_lines.push(Line::from(format!(
-                            "  • {} -> {}.{} (UPDATE: {}, DELETE: {})",
-                            fk.from_col, fk.to_table, fk.to_col, fk.on_update, fk.on_delete
-                        )));
+                        let target = match &fk.to_col {
+                            Some(to_col) => format!("{}.{}", fk.to_table, to_col),
+                            None => fk.to_table.clone(),
+                        };
+                        let mut spans = vec![Span::raw(format!(
+                            "  • {} -> {} (UPDATE: {}, DELETE: {})",
+                            fk.from_col, target, fk.on_update, fk.on_delete
+                        ))];
+                        let unindexed = app.unindexed.iter().find(|u| {
+                            u.to_table == fk.to_table && u.columns.contains(&fk.from_col)
+                        });
+                        if let Some(unindexed) = unindexed {
+                            let warning = match unindexed.columns.len() {
+                                1 => "  ⚠ no index starts with this column",
+                                _ => "  ⚠ no index starts with the columns of this key",
+                            };
+                            spans.push(Span::styled(warning, Style::default().fg(Color::Red)));
+                        }
+                        all_lines.push(Line::from(spans));
                     }
                     all_lines.push(Line::from(""));
                 }
@@ -312,16 +554,26 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                     .scroll((app.scroll_offset, 0));
                 // Don't wrap - let long lines scroll off screen
 
-                f.render_widget(paragraph, chunks[1]);
+                f.render_widget(paragraph, chunks[4]);
             } else {
                 let paragraph = Paragraph::new("Loading table details...")
                     .block(Block::default().borders(Borders::ALL))
                     .style(Style::default().bg(Color::Reset));
-                f.render_widget(paragraph, chunks[1]);
+                f.render_widget(paragraph, chunks[4]);
             }
         }
     }
 
+    if history_height > 0 {
+        let line = match app.selected_table() {
+            Some(table) => history_line(app, table, units),
+            None => Line::from(""),
+        };
+        let panel =
+            Paragraph::new(line).block(Block::default().borders(Borders::ALL).title("History"));
+        f.render_widget(panel, chunks[5]);
+    }
+
     // Footer
     let (selected_info, nav_hint) = match &app.view_mode {
         ViewMode::Tables => {
@@ -329,12 +581,28 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                 // Subtract 2 to account for header rows
                 if i >= 2 {
                     if let Some(table) = app.tables.get(i - 2) {
-                        format!(
-                            "Selected: {} ({}, {} rows)",
+                        let mut info = format!(
+                            "Selected: {} ({}, {} rows, pages: {} interior / {} leaf / {} overflow, {} unused/page)",
                             table.name,
-                            format_bytes(table.size_bytes),
-                            format_number(table.row_count)
-                        )
+                            units.format(table.size_bytes),
+                            format_row_count(table),
+                            format_number(table.pages.interior_pages),
+                            format_number(table.pages.leaf_pages),
+                            format_number(table.pages.overflow_pages),
+                            units.format(table.pages.avg_unused_per_page())
+                        );
+                        if let Some(error) = &table.error {
+                            info.push_str(&format!(" | Error: {}", error));
+                        }
+                        if let Some(budget) = &app.budget {
+                            for violation in budget.check_table(table) {
+                                info.push_str(&format!(
+                                    " | Over budget: {}",
+                                    violation.describe(units)
+                                ));
+                            }
+                        }
+                        info
                     } else {
                         String::new()
                     }
@@ -344,7 +612,10 @@ pub fn ui(f: &mut Frame, app: &mut App) {
             } else {
                 String::new()
             };
-            (info, "Enter: indexes | i: info | q: quit | ↑↓: navigate")
+            (
+                info,
+                "Enter: indexes | i: info | f: findings | c/C: exact rows | q: quit | ↑↓: navigate",
+            )
         }
         ViewMode::Indexes(_) => {
             let info = if let Some(i) = app.list_state.selected() {
@@ -352,13 +623,46 @@ pub fn ui(f: &mut Frame, app: &mut App) {
                 if i >= 2 {
                     if let Some(index) = app.indexes.get(i - 2) {
                         let mut info_parts = vec![format!(
-                            "Selected: {} ({})",
+                            "Selected: {} ({}, pages: {} interior / {} leaf / {} overflow, {} unused/page)",
                             index.name,
-                            format_bytes(index.size_bytes)
+                            units.format(index.size_bytes),
+                            format_number(index.pages.interior_pages),
+                            format_number(index.pages.leaf_pages),
+                            format_number(index.pages.overflow_pages),
+                            units.format(index.pages.avg_unused_per_page())
                         )];
+                        if let Some(constraint) = index.origin.constraint() {
+                            info_parts.push(format!("Created for a {} constraint", constraint));
+                        }
                         if let Some(partial) = &index.partial_clause {
                             info_parts.push(format!("WHERE {}", partial));
                         }
+                        if let Some(redundant) =
+                            app.redundant.iter().find(|r| r.index == index.name)
+                        {
+                            info_parts.push(format!("Redundant: {}", redundant.explain()));
+                        }
+                        if let Some(usage) = &app.usage {
+                            info_parts.push(match usage.hits(&index.name) {
+                                // Still needed to enforce the constraint
+                                0 if index.is_unique => format!(
+                                    "Workload: never used by {} statements, but enforces uniqueness",
+                                    format_number(usage.statements)
+                                ),
+                                0 => format!(
+                                    "Workload: never used by {} statements",
+                                    format_number(usage.statements)
+                                ),
+                                n => format!(
+                                    "Workload: used by {} of {} statements",
+                                    format_number(n),
+                                    format_number(usage.statements)
+                                ),
+                            });
+                        }
+                        if let Some(error) = &index.error {
+                            info_parts.push(format!("Error: {}", error));
+                        }
                         info_parts.join(" | ")
                     } else {
                         String::new()
@@ -375,15 +679,20 @@ pub fn ui(f: &mut Frame, app: &mut App) {
             )
         }
         ViewMode::TableInfo(_) => (String::new(), "Backspace: back to tables | q: quit"),
+        ViewMode::Diff => (String::new(), "q: quit | ↑↓: navigate"),
+        ViewMode::Findings => (
+            String::new(),
+            "Backspace: back to tables | q: quit | ↑↓: navigate",
+        ),
     };
 
     let footer_text = if selected_info.is_empty() {
-        format!("Total: {} | {}", format_bytes(app.total_size), nav_hint)
+        format!("Total: {} | {}", units.format(app.total_size), nav_hint)
     } else {
         format!(
             "{} | Total: {} | {}",
             selected_info,
-            format_bytes(app.total_size),
+            units.format(app.total_size),
             nav_hint
         )
     };
@@ -391,5 +700,104 @@ pub fn ui(f: &mut Frame, app: &mut App) {
     let footer = Paragraph::new(footer_text)
         .block(Block::default().borders(Borders::ALL))
         .style(Style::default().fg(Color::Gray));
-    f.render_widget(footer, chunks[2]);
+    f.render_widget(footer, chunks[6]);
+}
+
+/// Recorded analyses, growth rate and projection for one table.
+/// Names the workload the index markers come from, if there is one.
+fn indexes_title(table_name: &str, usage: Option<&IndexUsage>) -> String {
+    match usage {
+        None => format!("Indexes for {}", table_name),
+        Some(usage) if usage.failed.is_empty() => format!(
+            "Indexes for {} (workload of {} statements)",
+            table_name,
+            format_number(usage.statements)
+        ),
+        Some(usage) => format!(
+            "Indexes for {} (workload of {} statements, {} could not be planned)",
+            table_name,
+            format_number(usage.statements),
+            format_number(usage.failed.len() as u64)
+        ),
+    }
+}
+
+fn history_line(app: &App, table: &TableInfo, units: UnitStyle) -> Line<'static> {
+    let label = Style::default().fg(Color::Yellow);
+    let separator = Span::styled("  │  ", Style::default().fg(Color::DarkGray));
+    let series = app.table_series(table);
+
+    let mut spans = vec![
+        Span::styled(format!("{} ", table.name), label),
+        Span::raw(format!(
+            "{} analyses since {}",
+            series.points.len(),
+            format_date(series.points[0].0)
+        )),
+        separator.clone(),
+    ];
+    match series.growth_per_day() {
+        Some(per_day) => {
+            spans.push(Span::styled("Growth ", label));
+            spans.push(Span::raw(format!(
+                "{}/day",
+                units.format_delta(per_day.round() as i64)
+            )));
+            spans.push(separator);
+
+            let target = app
+                .target_size
+                .unwrap_or(table.size_bytes.saturating_mul(2));
+            spans.push(Span::styled(
+                format!("Reaches {} ", units.format(target)),
+                label,
+            ));
+            spans.push(Span::raw(match series.projected_time(target) {
+                Some(at) => format!("around {}", format_date(at)),
+                None if table.size_bytes >= target => String::from("already"),
+                None => String::from("never at this rate"),
+            }));
+        }
+        None => spans.push(Span::raw("Not enough history for a growth rate")),
+    }
+
+    Line::from(spans)
+}
+
+fn diff_summary_line(diff: &DatabaseDiff, units: UnitStyle) -> Line<'static> {
+    let label = Style::default().fg(Color::Yellow);
+    Line::from(vec![
+        Span::styled("File ", label),
+        Span::raw(change_summary(&diff.file, units)),
+        Span::styled("  │  ", Style::default().fg(Color::DarkGray)),
+        Span::styled("Freelist ", label),
+        Span::raw(change_summary(&diff.freelist, units)),
+    ])
+}
+
+/// One row of the diff listing, new objects in green and dropped ones in red.
+fn diff_item(object: &ObjectDiff, indent: &str, units: UnitStyle) -> ListItem<'static> {
+    let content = format!(
+        "{}  {}{}{}",
+        diff_columns(object, units),
+        indent,
+        object.name,
+        status_suffix(object.status)
+    );
+    let style = match object.status {
+        DiffStatus::Added => Style::default().fg(Color::Green),
+        DiffStatus::Dropped => Style::default().fg(Color::Red),
+        DiffStatus::Changed => Style::default(),
+        DiffStatus::Unchanged => Style::default().fg(Color::DarkGray),
+    };
+    ListItem::new(content).style(style)
+}
+
+/// Shows rows whose table or index could not be fully measured in red.
+fn error_style<'a>(item: ListItem<'a>, error: &Option<String>) -> ListItem<'a> {
+    if error.is_some() {
+        item.style(Style::default().fg(Color::Red))
+    } else {
+        item
+    }
 }
@@ -0,0 +1,220 @@
+//! Runs database analysis on a background thread so the TUI can open immediately.
+//!
+//! The worker first measures every table in one pass, then counts rows on a small
+//! pool of read-only connections, reporting each step over a channel. Row counting
+//! is the slow part on large databases, so the running `COUNT(*)` statements can be
+//! skipped or the whole analysis cancelled through SQLite's interrupt handles.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rusqlite::{ErrorCode, InterruptHandle};
+
+use crate::app::{
+    count_rows, measure, open_database, AnalysisOptions, RowCountMode, SpaceSummary, TableInfo,
+};
+use crate::error::{Result, SqduError};
+
+pub enum AnalysisEvent {
+    /// Sizes and row estimates are known for every table.
+    Measured {
+        tables: Vec<TableInfo>,
+        space: SpaceSummary,
+    },
+    /// Exact row counts follow for this many tables.
+    CountingRows(usize),
+    /// Started counting the rows of a table. Several tables can be counted at once.
+    Counting(String),
+    /// Finished counting a table; `None` when the count was skipped or failed.
+    Counted {
+        name: String,
+        row_count: Option<u64>,
+        /// Why the count failed; not set when it was skipped on request.
+        error: Option<String>,
+    },
+    Finished,
+    Cancelled,
+    Failed(String),
+}
+
+pub struct AnalysisHandle {
+    pub events: Receiver<AnalysisEvent>,
+    control: Arc<Control>,
+}
+
+impl AnalysisHandle {
+    /// Interrupts the row counts that are currently running; analysis carries on
+    /// with the remaining tables.
+    pub fn skip_current(&self) {
+        self.control.interrupt_all();
+    }
+
+    /// Stops the analysis. Tables that were not counted keep a skipped row count.
+    pub fn cancel(&self) {
+        self.control.cancelled.store(true, Ordering::SeqCst);
+        self.control.interrupt_all();
+    }
+}
+
+/// State shared between a running analysis and its [`AnalysisHandle`].
+#[derive(Default)]
+struct Control {
+    /// One handle per open connection of the analysis.
+    interrupts: Mutex<Vec<InterruptHandle>>,
+    cancelled: AtomicBool,
+}
+
+impl Control {
+    fn interrupt_all(&self) {
+        for handle in self.interrupts.lock().unwrap().iter() {
+            handle.interrupt();
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// What a worker thread has been asked to do.
+enum Job {
+    Analyze(AnalysisOptions),
+    CountRows(Vec<String>, AnalysisOptions),
+}
+
+/// Measures the database, then counts the rows of every table unless the
+/// options ask for estimates only.
+pub fn spawn_analysis(db_path: String, options: AnalysisOptions) -> AnalysisHandle {
+    spawn(db_path, Job::Analyze(options))
+}
+
+/// Computes exact row counts for the given tables of an already measured
+/// database, on up to `options.jobs` connections at once.
+pub fn spawn_row_counts(
+    db_path: String,
+    table_names: Vec<String>,
+    options: AnalysisOptions,
+) -> AnalysisHandle {
+    spawn(db_path, Job::CountRows(table_names, options))
+}
+
+fn spawn(db_path: String, job: Job) -> AnalysisHandle {
+    let (tx, rx) = mpsc::channel();
+    let control = Arc::new(Control::default());
+
+    let worker_control = Arc::clone(&control);
+    thread::spawn(move || {
+        let event = match run(&db_path, job, &tx, &worker_control) {
+            Ok(()) if worker_control.is_cancelled() => AnalysisEvent::Cancelled,
+            Ok(()) => AnalysisEvent::Finished,
+            Err(_) if worker_control.is_cancelled() => AnalysisEvent::Cancelled,
+            Err(e) => AnalysisEvent::Failed(e.to_string()),
+        };
+        let _ = tx.send(event);
+    });
+
+    AnalysisHandle {
+        events: rx,
+        control,
+    }
+}
+
+fn run(db_path: &str, job: Job, tx: &Sender<AnalysisEvent>, control: &Control) -> Result<()> {
+    let (names, options) = match job {
+        Job::Analyze(options) => {
+            let conn = open_database(db_path, options.open)?;
+            control
+                .interrupts
+                .lock()
+                .unwrap()
+                .push(conn.get_interrupt_handle());
+
+            let (tables, space) = measure(&conn, db_path, options)?;
+            let names: Vec<String> = tables.iter().map(|t| t.name.clone()).collect();
+            if tx.send(AnalysisEvent::Measured { tables, space }).is_err() {
+                // The receiving side is gone, nobody is waiting for row counts
+                return Ok(());
+            }
+            match options.row_counts {
+                RowCountMode::Exact => (names, options),
+                RowCountMode::Estimate => return Ok(()),
+            }
+        }
+        Job::CountRows(names, options) => (names, options),
+    };
+
+    let _ = tx.send(AnalysisEvent::CountingRows(names.len()));
+    count_with(db_path, names, options, control, &|event| {
+        let _ = tx.send(event);
+    })
+}
+
+/// Counts the rows of `names` on up to `options.jobs` read-only connections at once.
+///
+/// `on_event` receives a [`AnalysisEvent::Counting`] when a table is started and a
+/// [`AnalysisEvent::Counted`] when it is done, from whichever thread counted it.
+pub fn count_rows_parallel<F>(
+    db_path: &str,
+    names: Vec<String>,
+    options: AnalysisOptions,
+    on_event: F,
+) -> Result<()>
+where
+    F: Fn(AnalysisEvent) + Sync,
+{
+    count_with(db_path, names, options, &Control::default(), &on_event)
+}
+
+fn count_with(
+    db_path: &str,
+    names: Vec<String>,
+    options: AnalysisOptions,
+    control: &Control,
+    on_event: &(dyn Fn(AnalysisEvent) + Sync),
+) -> Result<()> {
+    let workers = options.jobs.clamp(1, names.len().max(1));
+
+    // Open every connection up front so a database that cannot be opened fails
+    // the analysis instead of leaving tables uncounted
+    let conns = (0..workers)
+        .map(|_| open_database(db_path, options.open))
+        .collect::<Result<Vec<_>>>()?;
+    control
+        .interrupts
+        .lock()
+        .unwrap()
+        .extend(conns.iter().map(|c| c.get_interrupt_handle()));
+
+    let queue = Mutex::new(names.into_iter());
+    thread::scope(|scope| {
+        for conn in conns {
+            let queue = &queue;
+            scope.spawn(move || loop {
+                if control.is_cancelled() {
+                    break;
+                }
+                let Some(name) = queue.lock().unwrap().next() else {
+                    break;
+                };
+
+                on_event(AnalysisEvent::Counting(name.clone()));
+                let (row_count, error) = match count_rows(&conn, &name) {
+                    Ok(n) => (Some(n), None),
+                    Err(e) if e.sqlite_error_code() == Some(ErrorCode::OperationInterrupted) => {
+                        (None, None)
+                    }
+                    Err(e) => (None, Some(SqduError::from(e).to_string())),
+                };
+                on_event(AnalysisEvent::Counted {
+                    name,
+                    row_count,
+                    error,
+                });
+            });
+        }
+    });
+
+    Ok(())
+}
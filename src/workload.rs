@@ -0,0 +1,179 @@
+//! Which indexes a query workload uses, from the plans SQLite chooses for it.
+//!
+//! A workload is a file of SQL statements separated by semicolons, such as an
+//! application's query log. Each statement is only planned with `EXPLAIN QUERY
+//! PLAN`, never run, so statements that would write are as safe to include as
+//! queries. Plans depend on the statistics `ANALYZE` left in `sqlite_stat1`, as
+//! they do for the application.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::error::Result;
+
+/// SQL statements to plan, in the order they were given.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Workload {
+    pub statements: Vec<String>,
+}
+
+/// A statement SQLite could not plan, e.g. one naming a table that does not exist.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FailedStatement {
+    pub sql: String,
+    pub error: String,
+}
+
+/// How often the plans of a workload use each index.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IndexUsage {
+    /// Statements planned, including those that failed.
+    pub statements: u64,
+    /// Statements whose plan uses each index; unused indexes are missing.
+    pub hits: HashMap<String, u64>,
+    pub failed: Vec<FailedStatement>,
+}
+
+impl IndexUsage {
+    /// Statements whose plan uses `index`; 0 when it was never used.
+    pub fn hits(&self, index: &str) -> u64 {
+        self.hits.get(index).copied().unwrap_or(0)
+    }
+}
+
+impl Workload {
+    /// Splits `sql` into statements at semicolons outside of strings, quoted
+    /// names and comments. Empty statements are dropped.
+    pub fn parse(sql: &str) -> Self {
+        let mut statements = Vec::new();
+        let mut current = String::new();
+        let mut chars = sql.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' | '"' | '`' | '[' => {
+                    let close = if c == '[' { ']' } else { c };
+                    current.push(c);
+                    // A doubled quote closes and reopens, which comes out the same
+                    for c in chars.by_ref() {
+                        current.push(c);
+                        if c == close {
+                            break;
+                        }
+                    }
+                }
+                '-' if chars.peek() == Some(&'-') => {
+                    // Comments are left out, so a commented-out statement is not planned
+                    for c in chars.by_ref() {
+                        if c == '\n' {
+                            current.push(c);
+                            break;
+                        }
+                    }
+                }
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    let mut previous = ' ';
+                    for c in chars.by_ref() {
+                        if previous == '*' && c == '/' {
+                            break;
+                        }
+                        previous = c;
+                    }
+                    current.push(' ');
+                }
+                ';' => {
+                    statements.push(std::mem::take(&mut current));
+                }
+                _ => current.push(c),
+            }
+        }
+        statements.push(current);
+
+        Self {
+            statements: statements
+                .into_iter()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+}
+
+/// Plans every statement of `workload` and counts the indexes each plan uses.
+/// A statement that cannot be planned is recorded in [`IndexUsage::failed`]
+/// rather than failing the whole workload.
+pub fn index_usage(conn: &Connection, workload: &Workload) -> Result<IndexUsage> {
+    let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'index'")?;
+    let mut names = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    // Longest first, so idx_a_b is not taken for idx_a
+    names.sort_by_key(|n| std::cmp::Reverse(n.len()));
+
+    let mut usage = IndexUsage::default();
+    // Logs repeat the same statements many times
+    let mut plans: HashMap<&str, std::result::Result<Vec<String>, String>> = HashMap::new();
+    for sql in &workload.statements {
+        usage.statements += 1;
+        let plan = plans
+            .entry(sql.as_str())
+            .or_insert_with(|| plan_indexes(conn, sql, &names).map_err(|e| e.to_string()));
+        match plan {
+            Ok(indexes) => {
+                for index in indexes.iter() {
+                    *usage.hits.entry(index.clone()).or_default() += 1;
+                }
+            }
+            Err(error) => usage.failed.push(FailedStatement {
+                sql: sql.clone(),
+                error: error.clone(),
+            }),
+        }
+    }
+    Ok(usage)
+}
+
+/// The indexes in the query plan of one statement, each named once.
+fn plan_indexes(conn: &Connection, sql: &str, names: &[String]) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql))?;
+    // Parameters from a log stay unbound; planning does not need their values
+    let mut rows = stmt.raw_query();
+    let mut used = Vec::new();
+    while let Some(row) = rows.next()? {
+        let detail: String = row.get(3)?;
+        for index in detail_indexes(&detail, names) {
+            if !used.iter().any(|u| u == index) {
+                used.push(index.to_string());
+            }
+        }
+    }
+    Ok(used)
+}
+
+/// Indexes named in one line of a query plan, such as
+/// `SEARCH orders USING COVERING INDEX idx_orders_customer (customer_id=?)`.
+/// Names are matched against the schema since they may contain spaces.
+fn detail_indexes<'a>(detail: &'a str, names: &'a [String]) -> Vec<&'a str> {
+    detail
+        .match_indices("INDEX ")
+        .filter_map(|(i, keyword)| {
+            let rest = &detail[i + keyword.len()..];
+            names
+                .iter()
+                .find(|name| {
+                    rest.strip_prefix(name.as_str())
+                        .is_some_and(|after| after.is_empty() || after.starts_with(' '))
+                })
+                .map(String::as_str)
+        })
+        .collect()
+}
@@ -1,5 +1,26 @@
-use sqdu::app::{analyze_database, analyze_indexes, analyze_table_details};
+use rusqlite::Connection;
+use sqdu::analyzer::Analyzer;
+use sqdu::app::{
+    analyze_database, analyze_database_with, analyze_indexes, analyze_space, analyze_table_details,
+    open_database, probe_dbstat, AnalysisOptions, AnalysisState, App, IndexOrigin, OpenOptions,
+    RowCount, RowCountMode, SortKey,
+};
+use sqdu::budget::{glob_match, Budget, TableBudget, Violation};
+use sqdu::diff::{DatabaseDiff, DiffStatus};
+use sqdu::error::SqduError;
+use sqdu::fileformat::DbFile;
+use sqdu::findings::Redundancy;
+use sqdu::format::{parse_size, UnitStyle};
+use sqdu::history::{format_date, Series};
+use sqdu::output::{render, render_diff, write_atomically, OutputFormat};
+use sqdu::report::{DatabaseReport, SCHEMA_VERSION};
+use sqdu::snapshot::Snapshot;
+use sqdu::worker::{count_rows_parallel, spawn_analysis, spawn_row_counts, AnalysisEvent};
+use sqdu::workload::Workload;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
 
 fn get_northwind_path() -> String {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -9,6 +30,30 @@ fn get_northwind_path() -> String {
     path.to_str().unwrap().to_string()
 }
 
+/// Builds a throwaway database in the temp directory from a batch of SQL.
+fn create_test_db(name: &str, sql: &str) -> String {
+    let mut path = std::env::temp_dir();
+    path.push(format!("sqdu-test-{}-{}.db", std::process::id(), name));
+    let _ = std::fs::remove_file(&path);
+
+    let conn = Connection::open(&path).unwrap();
+    conn.execute_batch(sql).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn create_blob_db(name: &str) -> String {
+    create_test_db(
+        name,
+        "CREATE TABLE blobs (id INTEGER PRIMARY KEY, data BLOB);
+         CREATE TABLE small (id INTEGER PRIMARY KEY, label TEXT);
+         CREATE INDEX idx_small_label ON small(label);
+         WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 50)
+         INSERT INTO blobs (data) SELECT zeroblob(20000) FROM n;
+         WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+         INSERT INTO small (label) SELECT 'label ' || i FROM n;",
+    )
+}
+
 #[test]
 fn test_analyze_database_returns_tables() {
     let db_path = get_northwind_path();
@@ -145,9 +190,10 @@ fn test_indexes_have_valid_names() {
 
         for index in &indexes {
             assert!(!index.name.is_empty(), "Index name should not be empty");
-            assert!(
-                !index.name.starts_with("sqlite_autoindex"),
-                "Should filter sqlite internal indexes"
+            assert_eq!(
+                index.name.starts_with("sqlite_autoindex"),
+                index.origin.constraint().is_some(),
+                "Automatic indexes should be attributed to their constraint"
             );
         }
     }
@@ -170,6 +216,44 @@ fn test_indexes_have_columns() {
     }
 }
 
+#[test]
+fn test_index_definitions() {
+    let db_path = create_test_db(
+        "index-definitions",
+        "CREATE TABLE \"UNIQUE (odd)\" (id INTEGER PRIMARY KEY, \"where\" TEXT, kind TEXT, n INT);
+         CREATE INDEX \"idx (where, x)\" ON \"UNIQUE (odd)\"(\"where\" DESC, kind COLLATE NOCASE);
+         CREATE UNIQUE INDEX idx_expr ON \"UNIQUE (odd)\"(lower(kind) COLLATE NOCASE DESC, n)
+             WHERE kind != ',)'
+                 AND n > 0;",
+    );
+    let indexes = analyze_indexes(&db_path, "UNIQUE (odd)").unwrap();
+    assert_eq!(indexes.len(), 2);
+
+    let quoted = indexes.iter().find(|i| i.name == "idx (where, x)").unwrap();
+    assert!(!quoted.is_unique);
+    assert!(!quoted.is_partial);
+    assert_eq!(quoted.partial_clause, None);
+    assert_eq!(quoted.origin, IndexOrigin::CreateIndex);
+    assert_eq!(quoted.columns, "where DESC, kind COLLATE NOCASE");
+    assert_eq!(quoted.key_columns[0].name.as_deref(), Some("where"));
+    assert!(quoted.key_columns[0].descending);
+    assert_eq!(quoted.key_columns[1].collation, "NOCASE");
+
+    let expr = indexes.iter().find(|i| i.name == "idx_expr").unwrap();
+    assert!(expr.is_unique);
+    assert!(expr.is_partial);
+    assert_eq!(
+        expr.partial_clause.as_deref(),
+        Some("kind != ',)'\n                 AND n > 0")
+    );
+    assert_eq!(expr.key_columns[0].name, None);
+    assert_eq!(
+        expr.key_columns[0].expression.as_deref(),
+        Some("lower(kind)")
+    );
+    assert_eq!(expr.columns, "lower(kind) COLLATE NOCASE DESC, n");
+}
+
 #[test]
 fn test_indexes_sorted_by_size() {
     let db_path = get_northwind_path();
@@ -353,7 +437,7 @@ fn test_foreign_keys_structure() {
                 "Foreign key to_table should not be empty"
             );
             assert!(
-                !fk.to_col.is_empty(),
+                fk.to_col.as_deref() != Some(""),
                 "Foreign key to_col should not be empty"
             );
             assert!(
@@ -573,3 +657,1338 @@ fn test_special_characters_in_table_names() {
         assert!(result.is_ok(), "Should handle table name: {}", table.name);
     }
 }
+
+#[test]
+fn test_quotes_in_table_names() {
+    let db_path = create_test_db(
+        "quoted-names",
+        "CREATE TABLE parent (id INTEGER PRIMARY KEY);
+         CREATE TABLE \"a\"\"b\" (id INTEGER PRIMARY KEY, parent_id INT REFERENCES parent(id));
+         INSERT INTO \"a\"\"b\" (parent_id) VALUES (1), (2), (3);",
+    );
+    let tables = analyze_database(&db_path).unwrap();
+    let quoted = tables.iter().find(|t| t.name == "a\"b").unwrap();
+    assert_eq!(quoted.row_count_kind, RowCount::Exact);
+    assert_eq!(quoted.row_count, 3);
+    assert_eq!(quoted.error, None);
+
+    let details = analyze_table_details(&db_path, "a\"b").unwrap();
+    assert_eq!(details.columns.len(), 2);
+    assert_eq!(details.foreign_keys[0].from_col, "parent_id");
+}
+
+#[test]
+fn test_page_type_breakdown() {
+    let db_path = create_blob_db("page-types");
+    let tables = analyze_database(&db_path).unwrap();
+
+    let blobs = tables.iter().find(|t| t.name == "blobs").unwrap();
+    assert!(
+        blobs.pages.overflow_pages > 0,
+        "BLOB payloads should spill onto overflow pages"
+    );
+    assert!(blobs.pages.overflow_bytes > blobs.pages.leaf_bytes);
+
+    let small = tables.iter().find(|t| t.name == "small").unwrap();
+    assert_eq!(small.pages.overflow_pages, 0);
+    assert!(
+        small.pages.interior_pages > 0,
+        "2000 rows need an interior page"
+    );
+
+    for table in &tables {
+        assert_eq!(table.size_bytes, table.pages.total_bytes());
+    }
+
+    let indexes = analyze_indexes(&db_path, "small").unwrap();
+    assert_eq!(indexes.len(), 1);
+    assert!(indexes[0].pages.leaf_pages > 0);
+    assert_eq!(indexes[0].size_bytes, indexes[0].pages.total_bytes());
+}
+
+#[test]
+fn test_fill_factor_after_deletes() {
+    let db_path = create_blob_db("fill-factor");
+    let tables = analyze_database(&db_path).unwrap();
+    let before = tables.iter().find(|t| t.name == "small").unwrap().clone();
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute("DELETE FROM small WHERE id % 4 != 0", [])
+        .unwrap();
+    drop(conn);
+
+    let tables = analyze_database(&db_path).unwrap();
+    let after = tables.iter().find(|t| t.name == "small").unwrap();
+    assert!(after.pages.payload_bytes > 0);
+    assert!(after.pages.payload_bytes < before.pages.payload_bytes);
+    assert!(
+        after.pages.efficiency() < before.pages.efficiency(),
+        "Deleting rows should lower the fill factor ({:.2} -> {:.2})",
+        before.pages.efficiency(),
+        after.pages.efficiency()
+    );
+    assert!(after.pages.avg_unused_per_page() > before.pages.avg_unused_per_page());
+}
+
+#[test]
+fn test_space_summary_reconciles_file_size() {
+    let db_path = create_blob_db("space-summary");
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute("DELETE FROM blobs WHERE id > 10", []).unwrap();
+    drop(conn);
+
+    let space = analyze_space(&db_path).unwrap();
+    let file_len = std::fs::metadata(&db_path).unwrap().len();
+    assert_eq!(space.file_bytes(), file_len);
+    assert!(
+        space.freelist_bytes > 0,
+        "Deleted BLOBs should land on the freelist"
+    );
+    assert!(
+        space.internal_bytes >= space.page_size,
+        "Schema page is internal"
+    );
+    assert_eq!(
+        space.table_bytes
+            + space.index_bytes
+            + space.internal_bytes
+            + space.freelist_bytes
+            + space.other_bytes(),
+        space.file_bytes()
+    );
+
+    let tables = analyze_database(&db_path).unwrap();
+    let table_total: u64 = tables.iter().map(|t| t.size_bytes).sum();
+    let index_total: u64 = tables.iter().map(|t| t.index_size_bytes).sum();
+    assert_eq!(space.table_bytes, table_total);
+    assert_eq!(space.index_bytes, index_total);
+}
+
+#[test]
+fn test_space_summary_counts_auto_indexes() {
+    let db_path = create_test_db(
+        "space-auto-indexes",
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE);
+         INSERT INTO users (email) VALUES ('a@example.com'), ('b@example.com');",
+    );
+    let space = analyze_space(&db_path).unwrap();
+    let tables = analyze_database(&db_path).unwrap();
+    // The index behind the UNIQUE constraint is the user's, not SQLite's own
+    assert!(space.index_bytes > 0);
+    assert_eq!(space.index_bytes, tables[0].index_size_bytes);
+}
+
+#[test]
+fn test_file_reader_matches_dbstat() {
+    let db_path = create_blob_db("file-reader");
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute("DELETE FROM small WHERE id % 3 = 0", [])
+        .unwrap();
+    let roots: Vec<(String, u32)> = conn
+        .prepare("SELECT name, rootpage FROM sqlite_master WHERE rootpage > 0")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    drop(conn);
+
+    // Without dbstat the analysis itself uses the file reader, so there would
+    // be nothing independent to compare with
+    if !probe_dbstat(&db_path).unwrap() {
+        return;
+    }
+
+    let mut file = DbFile::open(&db_path).unwrap();
+    assert_eq!(file.header().page_size, 4096);
+
+    let tables = analyze_database(&db_path).unwrap();
+    let indexes = analyze_indexes(&db_path, "small").unwrap();
+
+    for (name, root) in roots {
+        let walked = file.btree_stats(root).unwrap();
+        let expected = tables
+            .iter()
+            .find(|t| t.name == name)
+            .map(|t| t.pages.clone())
+            .or_else(|| {
+                indexes
+                    .iter()
+                    .find(|i| i.name == name)
+                    .map(|i| i.pages.clone())
+            })
+            .unwrap();
+
+        assert_eq!(walked.interior_pages, expected.interior_pages, "{}", name);
+        assert_eq!(walked.leaf_pages, expected.leaf_pages, "{}", name);
+        assert_eq!(walked.overflow_pages, expected.overflow_pages, "{}", name);
+        assert_eq!(walked.total_bytes(), expected.total_bytes(), "{}", name);
+        assert_eq!(walked.payload_bytes, expected.payload_bytes, "{}", name);
+        assert_eq!(walked.unused_bytes, expected.unused_bytes, "{}", name);
+        assert_eq!(walked.leaf_cells, expected.leaf_cells, "{}", name);
+    }
+}
+
+#[test]
+fn test_file_reader_accounts_for_every_page() {
+    let db_path = create_blob_db("file-reader-pages");
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute("DELETE FROM blobs WHERE id > 40", []).unwrap();
+    let roots: Vec<(String, u32)> = conn
+        .prepare("SELECT name, rootpage FROM sqlite_master WHERE rootpage > 0")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let count = |table: &str| -> u64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    };
+    let (blob_rows, small_rows) = (count("blobs"), count("small"));
+    drop(conn);
+
+    // Checked against the file itself rather than dbstat, which the system
+    // SQLite may not have
+    let mut file = DbFile::open(&db_path).unwrap();
+    let header = file.header().clone();
+    assert!(header.freelist_count > 0);
+
+    let mut pages = file.btree_stats(1).unwrap().total_pages();
+    for (name, root) in &roots {
+        let stats = file.btree_stats(*root).unwrap();
+        pages += stats.total_pages();
+        match name.as_str() {
+            "blobs" => {
+                assert_eq!(stats.leaf_cells, blob_rows);
+                assert!(stats.overflow_pages > 0, "BLOBs spill onto overflow pages");
+            }
+            "small" => assert_eq!(stats.leaf_cells, small_rows),
+            _ => {}
+        }
+        assert!(stats.payload_bytes + stats.unused_bytes <= stats.total_bytes());
+    }
+    assert_eq!(
+        pages + header.freelist_count as u64,
+        header.page_count as u64,
+        "every page belongs to a b-tree or the freelist"
+    );
+}
+
+#[test]
+fn test_file_reader_rejects_non_database() {
+    let mut path = std::env::temp_dir();
+    path.push(format!("sqdu-test-{}-not-a-db.txt", std::process::id()));
+    std::fs::write(&path, vec![b'x'; 4096]).unwrap();
+
+    assert!(DbFile::open(&path).is_err());
+}
+
+#[test]
+fn test_probe_dbstat() {
+    let db_path = create_blob_db("probe-dbstat");
+
+    // Only the bundled SQLite is sure to have dbstat; analysis falls back otherwise
+    let has_dbstat = probe_dbstat(&db_path).unwrap();
+    if cfg!(feature = "bundled") {
+        assert!(has_dbstat);
+    }
+    assert!(probe_dbstat("/nonexistent/path/to/database.db").is_err());
+}
+
+#[test]
+fn test_many_tables_single_scan() {
+    let mut sql = String::new();
+    for i in 0..300 {
+        sql.push_str(&format!(
+            "CREATE TABLE t{i} (id INTEGER PRIMARY KEY, v TEXT UNIQUE);
+             CREATE INDEX idx_t{i}_v ON t{i}(v);
+             INSERT INTO t{i} (v) VALUES ('a'), ('b'), ('c');"
+        ));
+    }
+    let db_path = create_test_db("many-tables", &sql);
+
+    let tables = analyze_database(&db_path).unwrap();
+    assert_eq!(tables.len(), 300);
+
+    for table in tables.iter().take(10) {
+        assert_eq!(table.row_count, 3);
+        assert_eq!(table.index_count, 2, "autoindexes are counted");
+
+        let indexes = analyze_indexes(&db_path, &table.name).unwrap();
+        let index_bytes: u64 = indexes.iter().map(|i| i.size_bytes).sum();
+        assert_eq!(table.index_size_bytes, index_bytes);
+    }
+}
+
+#[test]
+fn test_auto_indexes() {
+    let db_path = create_test_db(
+        "auto-indexes",
+        "CREATE TABLE users (id INTEGER PRIMARY KEY, email TEXT UNIQUE, name TEXT);
+         CREATE TABLE tags (name TEXT PRIMARY KEY, n INT) WITHOUT ROWID;
+         CREATE TABLE pairs (a INT, b INT, PRIMARY KEY (a, b));
+         CREATE INDEX idx_users_name ON users(name);
+         INSERT INTO users (email, name) VALUES ('a@example.com', 'a'), ('b@example.com', 'b');",
+    );
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    let users = analyzer
+        .tables()
+        .unwrap()
+        .iter()
+        .find(|t| t.name == "users")
+        .unwrap();
+    assert_eq!(users.index_count, 2);
+
+    let indexes = analyzer.indexes("users").unwrap();
+    let email = indexes.iter().find(|i| i.columns == "email").unwrap();
+    assert!(email.name.starts_with("sqlite_autoindex_users"));
+    assert_eq!(email.origin, IndexOrigin::Unique);
+    assert!(email.is_unique);
+    let pairs = analyzer.indexes("pairs").unwrap();
+    assert_eq!(pairs[0].origin, IndexOrigin::PrimaryKey);
+    assert_eq!(pairs[0].columns, "a, b");
+    assert_eq!(
+        analyzer.indexes("tags").unwrap()[0].origin,
+        IndexOrigin::PrimaryKey
+    );
+
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+    let text = render(&report, OutputFormat::Text, UnitStyle::Binary);
+    assert!(text.contains("(email) UNIQUE constraint"), "{}", text);
+    assert!(text.contains("(a, b) PRIMARY KEY constraint"), "{}", text);
+
+    // Automatic indexes are attributed to indexes rather than internal objects
+    let index_total: u64 = analyzer
+        .tables()
+        .unwrap()
+        .iter()
+        .map(|t| t.index_size_bytes)
+        .sum();
+    let space = analyzer.space().unwrap();
+    assert_eq!(space.index_bytes, index_total);
+
+    let options = AnalysisOptions {
+        hide_auto_indexes: true,
+        ..AnalysisOptions::default()
+    };
+    let mut analyzer = Analyzer::open(&db_path, options).unwrap();
+    let users = analyzer
+        .tables()
+        .unwrap()
+        .iter()
+        .find(|t| t.name == "users")
+        .unwrap();
+    assert_eq!(users.index_count, 1);
+    let indexes = analyzer.indexes("users").unwrap();
+    assert_eq!(indexes.len(), 1);
+    assert_eq!(indexes[0].name, "idx_users_name");
+    assert!(analyzer.indexes("tags").unwrap().is_empty());
+}
+
+#[test]
+fn test_background_analysis_streams_row_counts() {
+    let db_path = create_blob_db("background");
+    let handle = spawn_analysis(db_path.clone(), AnalysisOptions::default());
+
+    let mut app = App::analyzing(db_path);
+    assert!(app.analysis.is_running());
+
+    while app.analysis.is_running() {
+        let event = handle
+            .events
+            .recv_timeout(Duration::from_secs(10))
+            .expect("analysis should keep reporting progress");
+        app.apply(event);
+    }
+
+    assert_eq!(app.analysis, AnalysisState::Done);
+    assert_eq!(app.tables.len(), 2);
+    assert!(app.space.file_bytes() > 0);
+    for table in &app.tables {
+        assert_eq!(table.row_count_kind, RowCount::Exact);
+    }
+    let small = app.tables.iter().find(|t| t.name == "small").unwrap();
+    assert_eq!(small.row_count, 2000);
+}
+
+#[test]
+fn test_background_analysis_cancel() {
+    let db_path = create_blob_db("cancel");
+    let handle = spawn_analysis(db_path.clone(), AnalysisOptions::default());
+    let mut app = App::analyzing(db_path);
+
+    while app.analysis.is_running() {
+        let event = handle.events.recv_timeout(Duration::from_secs(10)).unwrap();
+        let measured = matches!(event, AnalysisEvent::Measured { .. });
+        app.apply(event);
+        if measured {
+            handle.cancel();
+        }
+    }
+
+    // Depending on timing the worker may already be done with the tiny tables
+    assert!(matches!(
+        app.analysis,
+        AnalysisState::Cancelled | AnalysisState::Done
+    ));
+    assert!(app
+        .tables
+        .iter()
+        .all(|t| t.row_count_kind != RowCount::Pending));
+}
+
+fn estimate_only() -> AnalysisOptions {
+    AnalysisOptions {
+        row_counts: RowCountMode::Estimate,
+        ..AnalysisOptions::default()
+    }
+}
+
+#[test]
+fn test_estimated_row_counts() {
+    let db_path = create_blob_db("estimates");
+    let tables = analyze_database_with(&db_path, estimate_only()).unwrap();
+
+    for table in &tables {
+        assert_eq!(table.row_count_kind, RowCount::Estimated);
+    }
+    let small = tables.iter().find(|t| t.name == "small").unwrap();
+    assert_eq!(
+        small.row_count, 2000,
+        "leaf cells of a rowid table are its rows"
+    );
+    assert_eq!(small.pages.leaf_cells, 2000);
+}
+
+#[test]
+fn test_exact_count_on_demand() {
+    let db_path = create_blob_db("on-demand");
+    let handle = spawn_analysis(db_path.clone(), estimate_only());
+    let mut app = App::analyzing(db_path.clone());
+    while app.analysis.is_running() {
+        app.apply(handle.events.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+    assert!(app
+        .tables
+        .iter()
+        .all(|t| t.row_count_kind == RowCount::Estimated));
+
+    app.start_counting(1);
+    let handle = spawn_row_counts(
+        db_path,
+        vec!["blobs".to_string()],
+        AnalysisOptions::default(),
+    );
+    while app.analysis.is_running() {
+        app.apply(handle.events.recv_timeout(Duration::from_secs(10)).unwrap());
+    }
+
+    let blobs = app.tables.iter().find(|t| t.name == "blobs").unwrap();
+    assert_eq!(blobs.row_count_kind, RowCount::Exact);
+    assert_eq!(blobs.row_count, 50);
+    let small = app.tables.iter().find(|t| t.name == "small").unwrap();
+    assert_eq!(small.row_count_kind, RowCount::Estimated);
+}
+
+#[test]
+fn test_parallel_row_counts() {
+    let sql: String = (1..=8)
+        .map(|i| {
+            format!(
+                "CREATE TABLE t{i} (id INTEGER PRIMARY KEY, v TEXT);
+                 WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n WHERE x < {rows})
+                 INSERT INTO t{i} (v) SELECT 'row' || x FROM n;",
+                i = i,
+                rows = i * 250
+            )
+        })
+        .collect();
+    let db_path = create_test_db("parallel", &sql);
+
+    let names: Vec<String> = (1..=8).map(|i| format!("t{}", i)).collect();
+    let counts = Mutex::new(HashMap::new());
+    let options = AnalysisOptions {
+        jobs: 4,
+        ..AnalysisOptions::default()
+    };
+    count_rows_parallel(&db_path, names, options, |event| {
+        if let AnalysisEvent::Counted {
+            name, row_count, ..
+        } = event
+        {
+            counts.lock().unwrap().insert(name, row_count);
+        }
+    })
+    .unwrap();
+
+    let counts = counts.into_inner().unwrap();
+    assert_eq!(counts.len(), 8, "every table is counted exactly once");
+    for i in 1..=8u64 {
+        assert_eq!(counts[&format!("t{}", i)], Some(i * 250));
+    }
+
+    // The number of workers does not change the result
+    let sequential = analyze_database_with(
+        &db_path,
+        AnalysisOptions {
+            jobs: 1,
+            ..AnalysisOptions::default()
+        },
+    )
+    .unwrap();
+    let parallel = analyze_database_with(
+        &db_path,
+        AnalysisOptions {
+            jobs: 8,
+            ..AnalysisOptions::default()
+        },
+    )
+    .unwrap();
+    for (a, b) in sequential.iter().zip(&parallel) {
+        assert_eq!(a.name, b.name);
+        assert_eq!(a.row_count, b.row_count);
+        assert_eq!(b.row_count_kind, RowCount::Exact);
+    }
+}
+
+#[test]
+fn test_missing_database_is_not_created() {
+    let mut path = std::env::temp_dir();
+    path.push(format!("sqdu-test-{}-missing.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let db_path = path.to_str().unwrap();
+
+    assert!(matches!(
+        analyze_database(db_path),
+        Err(SqduError::NotFound(_))
+    ));
+    assert!(analyze_indexes(db_path, "t").is_err());
+    assert!(analyze_table_details(db_path, "t").is_err());
+    assert!(!path.exists(), "opening must never create a database");
+}
+
+#[test]
+fn test_open_rejects_non_database() {
+    let mut path = std::env::temp_dir();
+    path.push(format!("sqdu-test-{}-not-a-db.txt", std::process::id()));
+    std::fs::write(&path, "just some text, definitely not a database").unwrap();
+
+    let err = open_database(path.to_str().unwrap(), OpenOptions::default()).unwrap_err();
+    assert!(matches!(err, SqduError::NotADatabase(_)));
+    assert!(err.to_string().contains("not a SQLite database"));
+
+    // An empty file would be a valid new database to SQLite, but not to sqdu
+    std::fs::write(&path, "").unwrap();
+    assert!(open_database(path.to_str().unwrap(), OpenOptions::default()).is_err());
+}
+
+#[test]
+fn test_connections_are_read_only() {
+    let db_path = create_test_db("read-only", "CREATE TABLE t (x); INSERT INTO t VALUES (1);");
+    let before = std::fs::read(&db_path).unwrap();
+
+    for immutable in [false, true] {
+        let conn = open_database(
+            &db_path,
+            OpenOptions {
+                immutable,
+                ..OpenOptions::default()
+            },
+        )
+        .unwrap();
+        assert!(conn.execute("INSERT INTO t VALUES (2)", []).is_err());
+        assert!(conn.execute_batch("CREATE TABLE u (y)").is_err());
+        let rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM t", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+    }
+
+    let options = AnalysisOptions {
+        open: OpenOptions {
+            immutable: true,
+            ..OpenOptions::default()
+        },
+        ..AnalysisOptions::default()
+    };
+    let tables = analyze_database_with(&db_path, options).unwrap();
+    assert_eq!(tables[0].row_count, 1);
+    assert_eq!(std::fs::read(&db_path).unwrap(), before);
+}
+
+#[test]
+fn test_open_path_with_uri_characters() {
+    let db_path = create_test_db("odd#name?100%", "CREATE TABLE t (x);");
+    let conn = open_database(&db_path, OpenOptions::default()).unwrap();
+    let tables: i64 = conn
+        .query_row("SELECT COUNT(*) FROM sqlite_master", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(tables, 1);
+}
+
+#[test]
+fn test_snapshot_is_consistent_and_removed() {
+    let db_path = create_blob_db("snapshot");
+    let writer = Connection::open(&db_path).unwrap();
+    writer.pragma_update(None, "journal_mode", "wal").unwrap();
+
+    // A writer in the middle of a transaction does not block the snapshot
+    writer.execute_batch("BEGIN; DELETE FROM small;").unwrap();
+    let snapshot = Snapshot::create(&db_path, OpenOptions::default()).unwrap();
+    writer.execute_batch("COMMIT;").unwrap();
+
+    let path = std::path::PathBuf::from(snapshot.path());
+    assert!(path.exists());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "other users cannot read the copy");
+    }
+    let tables = analyze_database(snapshot.path()).unwrap();
+    let small = tables.iter().find(|t| t.name == "small").unwrap();
+    assert_eq!(small.row_count, 2000, "the snapshot predates the commit");
+
+    let original = analyze_database(&db_path).unwrap();
+    assert_eq!(
+        original
+            .iter()
+            .find(|t| t.name == "small")
+            .unwrap()
+            .row_count,
+        0
+    );
+
+    // As a crash would leave it
+    let journal = format!("{}-journal", snapshot.path());
+    std::fs::write(&journal, b"").unwrap();
+    drop(snapshot);
+    assert!(!path.exists(), "dropping a snapshot deletes its file");
+    assert!(!std::path::Path::new(&journal).exists());
+
+    // The report describes the original, not the copy
+    let options = AnalysisOptions {
+        snapshot: true,
+        ..AnalysisOptions::default()
+    };
+    let mut analyzer = Analyzer::open(&db_path, options).unwrap();
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+    assert!(report.database.snapshot);
+    assert_eq!(report.database.journal_mode, "wal");
+}
+
+#[test]
+fn test_busy_timeout_gives_up_on_locked_database() {
+    let db_path = create_test_db("busy", "CREATE TABLE t (x); INSERT INTO t VALUES (1);");
+    let writer = Connection::open(&db_path).unwrap();
+    writer.execute_batch("BEGIN EXCLUSIVE;").unwrap();
+
+    let options = OpenOptions {
+        busy_timeout: Duration::from_millis(50),
+        ..OpenOptions::default()
+    };
+    assert!(matches!(
+        Snapshot::create(&db_path, options),
+        Err(SqduError::Busy)
+    ));
+
+    writer.execute_batch("COMMIT;").unwrap();
+    assert!(Snapshot::create(&db_path, options).is_ok());
+}
+
+#[test]
+fn test_analyzer_caches_results() {
+    let db_path = create_blob_db("analyzer");
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    assert_eq!(analyzer.db_path(), db_path);
+
+    let small_rows = |analyzer: &mut Analyzer| {
+        let tables = analyzer.tables().unwrap();
+        tables.iter().find(|t| t.name == "small").unwrap().row_count
+    };
+    assert_eq!(small_rows(&mut analyzer), 2000);
+    assert_eq!(analyzer.indexes("small").unwrap().len(), 1);
+    assert_eq!(analyzer.table_details("small").unwrap().columns.len(), 2);
+
+    let writer = Connection::open(&db_path).unwrap();
+    writer
+        .execute_batch(
+            "DELETE FROM small WHERE id > 1000; CREATE INDEX idx_small_id ON small (id);",
+        )
+        .unwrap();
+
+    // Answers come from the cache until it is refreshed
+    assert_eq!(small_rows(&mut analyzer), 2000);
+    assert_eq!(analyzer.indexes("small").unwrap().len(), 1);
+
+    analyzer.refresh();
+    assert_eq!(small_rows(&mut analyzer), 1000);
+    assert_eq!(analyzer.indexes("small").unwrap().len(), 2);
+    assert!(analyzer.space().unwrap().file_bytes() > 0);
+}
+
+#[test]
+fn test_analyzer_snapshot() {
+    let db_path = create_blob_db("analyzer-snapshot");
+    let options = AnalysisOptions {
+        snapshot: true,
+        ..AnalysisOptions::default()
+    };
+    let mut analyzer = Analyzer::open(&db_path, options).unwrap();
+    assert!(analyzer.is_snapshot());
+    assert_ne!(analyzer.db_path(), db_path);
+    assert!(analyzer.options().open.immutable);
+    assert_eq!(analyzer.tables().unwrap().len(), 2);
+
+    let copy = std::path::PathBuf::from(analyzer.db_path());
+    drop(analyzer);
+    assert!(!copy.exists());
+}
+
+#[test]
+fn test_failed_row_count_is_reported_per_table() {
+    let db_path = create_blob_db("count-error");
+    let mut app = App::analyzing(db_path.clone());
+    app.apply(AnalysisEvent::Measured {
+        tables: analyze_database_with(&db_path, estimate_only()).unwrap(),
+        space: analyze_space(&db_path).unwrap(),
+    });
+    assert!(app.tables.iter().all(|t| t.error.is_none()));
+
+    app.start_counting(2);
+    app.apply(AnalysisEvent::Counted {
+        name: "small".to_string(),
+        row_count: None,
+        error: Some("database is corrupt: page 7".to_string()),
+    });
+    app.apply(AnalysisEvent::Counted {
+        name: "blobs".to_string(),
+        row_count: None,
+        error: None,
+    });
+
+    let small = app.tables.iter().find(|t| t.name == "small").unwrap();
+    assert_eq!(
+        small.row_count_kind,
+        RowCount::Estimated,
+        "keeps the estimate"
+    );
+    assert!(small.error.as_deref().unwrap().contains("corrupt"));
+    let blobs = app.tables.iter().find(|t| t.name == "blobs").unwrap();
+    assert!(blobs.error.is_none(), "a skipped count is not an error");
+}
+
+#[test]
+fn test_database_report() {
+    let db_path = create_blob_db("report");
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+
+    assert_eq!(report.schema_version, SCHEMA_VERSION);
+    assert_eq!(report.database.path, db_path);
+    assert_eq!(
+        report.database.file_size,
+        std::fs::metadata(&db_path).unwrap().len()
+    );
+    assert_eq!(report.database.encoding, "UTF-8");
+    assert_eq!(report.tables.len(), 2);
+
+    let small = report
+        .tables
+        .iter()
+        .find(|t| t.table.name == "small")
+        .unwrap();
+    assert_eq!(small.table.row_count, 2000);
+    assert_eq!(small.indexes.len(), 1);
+    assert_eq!(small.details.columns.len(), 2);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_report_json_round_trip() {
+    let db_path = create_blob_db("report-json");
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+
+    let json = report.to_json().unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["schema_version"], SCHEMA_VERSION);
+    // Table fields sit next to the nested indexes rather than under a sub-object
+    assert!(value["tables"][0]["size_bytes"].is_u64());
+    assert_eq!(value["tables"][0]["row_count_kind"], "exact");
+
+    let parsed = DatabaseReport::from_json(&json).unwrap();
+    assert_eq!(parsed.tables.len(), report.tables.len());
+    assert_eq!(parsed.space.file_bytes(), report.space.file_bytes());
+
+    let newer = json.replacen(
+        &format!("\"schema_version\": {}", SCHEMA_VERSION),
+        &format!("\"schema_version\": {}", SCHEMA_VERSION + 1),
+        1,
+    );
+    assert!(DatabaseReport::from_json(&newer).is_err());
+}
+
+#[test]
+fn test_report_output_formats() {
+    let db_path = create_blob_db("report-output");
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+
+    let text = render(&report, OutputFormat::Text, UnitStyle::Binary);
+    assert!(text.contains("blobs"));
+    assert!(text.contains("idx_small_label (label) INDEX"));
+
+    // One header, one record per table and one per index
+    let csv = render(&report, OutputFormat::Csv, UnitStyle::Binary);
+    let records: Vec<&str> = csv.split_terminator("\r\n").collect();
+    assert_eq!(records.len(), 1 + 2 + 1);
+    assert!(records[0].starts_with("kind,table,name,size_bytes,row_count"));
+    assert!(records
+        .iter()
+        .any(|r| r.starts_with("index,small,idx_small_label,")));
+    assert!(records.iter().any(|r| r.starts_with("table,small,small,")));
+
+    let markdown = render(&report, OutputFormat::Markdown, UnitStyle::Binary);
+    assert!(markdown.contains("| **blobs** |"));
+    assert!(markdown.contains("| ↳ idx_small_label (label) INDEX |"));
+
+    #[cfg(feature = "serde")]
+    {
+        let json = render(&report, OutputFormat::Json, UnitStyle::Binary);
+        let parsed = DatabaseReport::from_json(&json).unwrap();
+        assert_eq!(parsed.tables.len(), 2);
+    }
+}
+
+#[test]
+fn test_prometheus_output() {
+    let db_path = create_test_db(
+        "odd\"label",
+        "CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT);
+         CREATE INDEX idx_items_name ON items(name);
+         INSERT INTO items (name) VALUES ('a'), ('b');",
+    );
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+    let metrics = render(&report, OutputFormat::Prometheus, UnitStyle::Binary);
+
+    // Quotes in label values are escaped
+    let database = format!("database=\"{}\"", db_path.replace('"', "\\\""));
+    assert!(metrics.contains("# TYPE sqdu_table_size_bytes gauge"));
+    assert!(metrics.contains(&format!(
+        "sqdu_table_rows{{{},table=\"items\"}} 2\n",
+        database
+    )));
+    assert!(metrics.contains(&format!(
+        "sqdu_index_size_bytes{{{},table=\"items\",index=\"idx_items_name\"}} 4096\n",
+        database
+    )));
+    assert!(metrics.contains(&format!(
+        "sqdu_file_size_bytes{{{}}} {}\n",
+        database,
+        report.space.file_bytes()
+    )));
+    // Every family is declared once, before its samples
+    assert_eq!(metrics.matches("# TYPE sqdu_index_size_bytes").count(), 1);
+
+    let mut path = std::env::temp_dir();
+    path.push(format!("sqdu-test-{}.prom", std::process::id()));
+    write_atomically(&path, &metrics).unwrap();
+    write_atomically(&path, &metrics).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), metrics);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_include_internal_tables() {
+    let db_path = create_test_db(
+        "internal",
+        "CREATE TABLE items (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT);
+         INSERT INTO items (name) VALUES ('a'), ('b');",
+    );
+
+    let tables = analyze_database(&db_path).unwrap();
+    assert_eq!(tables.len(), 1);
+
+    let options = AnalysisOptions {
+        include_internal: true,
+        ..AnalysisOptions::default()
+    };
+    let tables = analyze_database_with(&db_path, options).unwrap();
+    let names: Vec<&str> = tables.iter().map(|t| t.name.as_str()).collect();
+    assert!(names.contains(&"items"));
+    assert!(names.contains(&"sqlite_sequence"));
+    let schema = tables.iter().find(|t| t.name == "sqlite_schema").unwrap();
+    assert!(schema.size_bytes > 0);
+    assert_eq!(schema.row_count, 2);
+}
+
+#[test]
+fn test_sort_keys() {
+    let db_path = create_blob_db("sort");
+    let mut tables = analyze_database(&db_path).unwrap();
+
+    tables.sort_by(|a, b| SortKey::Size.compare(a, b));
+    assert_eq!(tables[0].name, "blobs");
+    tables.sort_by(|a, b| SortKey::Rows.compare(a, b));
+    assert_eq!(tables[0].name, "small");
+    tables.sort_by(|a, b| SortKey::IndexSize.compare(a, b));
+    assert_eq!(tables[0].name, "small");
+    tables.sort_by(|a, b| SortKey::Name.compare(a, b));
+    assert_eq!(tables[0].name, "blobs");
+}
+
+#[test]
+fn test_unit_styles() {
+    assert_eq!(UnitStyle::Binary.format(1536), "1.5 KiB");
+    assert_eq!(UnitStyle::Decimal.format(1500), "1.5 kB");
+    assert_eq!(UnitStyle::Bytes.format(1536), "1,536 B");
+    assert_eq!(UnitStyle::Decimal.format(999), "999 B");
+}
+
+#[test]
+fn test_diff_between_databases() {
+    let old_path = create_test_db(
+        "diff-old",
+        "CREATE TABLE kept (id INTEGER PRIMARY KEY, label TEXT);
+         CREATE INDEX idx_kept_label ON kept(label);
+         CREATE TABLE gone (id INTEGER PRIMARY KEY);
+         INSERT INTO kept (label) VALUES ('a'), ('b');",
+    );
+    let new_path = create_test_db(
+        "diff-kept",
+        "CREATE TABLE kept (id INTEGER PRIMARY KEY, label TEXT);
+         CREATE INDEX idx_kept_label ON kept(label);
+         CREATE TABLE fresh (id INTEGER PRIMARY KEY);
+         WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+         INSERT INTO kept (label) SELECT 'label ' || i FROM n;",
+    );
+    let report = |path: &str| {
+        let mut analyzer = Analyzer::open(path, AnalysisOptions::default()).unwrap();
+        DatabaseReport::generate(&mut analyzer).unwrap()
+    };
+
+    let diff = DatabaseDiff::between(&report(&old_path), &report(&new_path));
+    let table = |name: &str| diff.tables.iter().find(|t| t.table.name == name).unwrap();
+
+    let kept = table("kept");
+    assert_eq!(kept.table.status, DiffStatus::Changed);
+    assert!(kept.table.size.delta() > 0);
+    assert_eq!(kept.table.rows.unwrap().delta(), 1998);
+    assert_eq!(kept.indexes.len(), 1);
+    assert_eq!(kept.indexes[0].status, DiffStatus::Changed);
+    assert_eq!(table("fresh").table.status, DiffStatus::Added);
+    assert_eq!(table("gone").table.status, DiffStatus::Dropped);
+    assert_eq!(table("gone").table.size.new, None);
+    // Largest change first
+    assert_eq!(diff.tables[0].table.name, "kept");
+
+    let text = render_diff(&diff, OutputFormat::Text, UnitStyle::Binary);
+    assert!(text.contains("fresh (new)"));
+    assert!(text.contains("gone (dropped)"));
+    assert!(text.contains("+1,998"));
+
+    // Nothing changed between a database and itself
+    let blob_path = create_blob_db("diff-same");
+    let same = DatabaseDiff::between(&report(&blob_path), &report(&blob_path));
+    assert!(same
+        .tables
+        .iter()
+        .all(|t| t.table.status == DiffStatus::Unchanged));
+}
+
+#[test]
+fn test_growth_series() {
+    const DAY: u64 = 86_400;
+    let series = Series {
+        points: vec![(0, 1000), (DAY, 2000), (2 * DAY, 3000)],
+    };
+    assert_eq!(series.growth_per_day().unwrap().round(), 1000.0);
+    // 7000 more bytes at 1000 per day from the last point
+    assert_eq!(series.projected_time(10_000), Some(9 * DAY));
+    assert_eq!(series.projected_time(2000), None);
+    assert_eq!(series.sparkline(12), "▁▅█");
+    assert_eq!(series.sparkline(2), "▁█");
+
+    let shrinking = series.clone().with_point(3 * DAY, 500);
+    assert_eq!(shrinking.points.len(), 4);
+    assert_eq!(shrinking.projected_time(10_000), None);
+
+    let single = Series {
+        points: vec![(DAY, 1000)],
+    };
+    assert_eq!(single.growth_per_day(), None);
+    assert_eq!(single.sparkline(12), "▁");
+
+    assert_eq!(format_date(0), "1970-01-01");
+    assert_eq!(format_date(1_709_251_200), "2024-03-01");
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("512"), Some(512));
+    assert_eq!(parse_size("10kB"), Some(10_000));
+    assert_eq!(parse_size("10KiB"), Some(10_240));
+    assert_eq!(parse_size("1.5 GB"), Some(1_500_000_000));
+    assert_eq!(parse_size("2g"), Some(2 << 30));
+    assert_eq!(parse_size("ten MB"), None);
+    assert_eq!(parse_size("10 parsecs"), None);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_history_store_round_trip() {
+    use sqdu::history::{HistoryEntry, HistoryStore};
+
+    let db_path = create_blob_db("history");
+    let other_path = create_test_db("history-other", "CREATE TABLE t (x);");
+    let mut store_path = std::env::temp_dir();
+    store_path.push(format!("sqdu-test-{}-history.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&store_path);
+    let store = HistoryStore::new(&store_path);
+
+    // Nothing recorded yet
+    assert!(store.load(&db_path).unwrap().is_empty());
+
+    let report = |path: &str| {
+        let mut analyzer = Analyzer::open(path, AnalysisOptions::default()).unwrap();
+        DatabaseReport::generate(&mut analyzer).unwrap()
+    };
+    let mut older = HistoryEntry::from_report(&report(&db_path));
+    older.recorded_at -= 86_400;
+    older.tables.iter_mut().for_each(|t| t.size_bytes /= 2);
+    store
+        .append(&HistoryEntry::from_report(&report(&db_path)))
+        .unwrap();
+    store.append(&older).unwrap();
+    store
+        .append(&HistoryEntry::from_report(&report(&other_path)))
+        .unwrap();
+
+    // Entries of other databases are left out and the rest come back oldest first
+    let history = store.load(&db_path).unwrap();
+    assert_eq!(history.entries.len(), 2);
+    assert!(history.entries[0].recorded_at < history.entries[1].recorded_at);
+    assert_eq!(history.entries[1].indexes["small"].len(), 1);
+
+    let series = history.series("blobs");
+    assert_eq!(series.points.len(), 2);
+    assert!(series.growth_per_day().unwrap() > 0.0);
+
+    let _ = std::fs::remove_file(&store_path);
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("*", "anything"));
+    assert!(glob_match("events_*", "events_2024"));
+    assert!(glob_match("events_*", "events_"));
+    assert!(!glob_match("events_*", "event"));
+    assert!(glob_match("log_??", "log_01"));
+    assert!(!glob_match("log_??", "log_001"));
+    assert!(glob_match("*_a*b", "x_aab_ab"));
+    assert!(!glob_match("small", "smaller"));
+}
+
+#[test]
+fn test_budget_check() {
+    let db_path = create_blob_db("budget");
+    let tables = analyze_database(&db_path).unwrap();
+    let budget = Budget {
+        max_file_size: Some(4096),
+        tables: vec![
+            TableBudget {
+                pattern: "b*".to_string(),
+                max_size: Some(100_000),
+                ..Default::default()
+            },
+            TableBudget {
+                pattern: "*".to_string(),
+                max_rows: Some(1000),
+                max_index_ratio: Some(100.0),
+                ..Default::default()
+            },
+        ],
+    };
+
+    let violations = budget.check(&tables, 1_000_000);
+    assert_eq!(violations.len(), 3, "{:?}", violations);
+    assert!(matches!(
+        violations[0],
+        Violation::FileSize {
+            actual: 1_000_000,
+            max: 4096
+        }
+    ));
+    assert!(violations
+        .iter()
+        .any(|v| matches!(v, Violation::TableSize { table, .. } if table == "blobs")));
+    assert!(violations
+        .iter()
+        .any(|v| matches!(v, Violation::Rows { table, actual: 2000, .. } if table == "small")));
+
+    // Row limits wait until rows are counted
+    let mut pending = tables.clone();
+    pending
+        .iter_mut()
+        .for_each(|t| t.row_count_kind = RowCount::Pending);
+    assert_eq!(budget.check(&pending, 0).len(), 1);
+
+    assert!(Budget::default().check(&tables, u64::MAX).is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_budget_from_toml() {
+    let budget = Budget::from_toml(
+        r#"
+        max_file_size = "2 GB"
+
+        [tables."events_*"]
+        max_size = 1024
+        max_rows = 10_000
+
+        [tables.users]
+        max_size = "1.5 MiB"
+        max_index_ratio = 0.5
+        "#,
+    )
+    .unwrap();
+    assert_eq!(budget.max_file_size, Some(2_000_000_000));
+    assert_eq!(budget.tables.len(), 2);
+    assert_eq!(budget.tables[0].pattern, "events_*");
+    assert_eq!(budget.tables[0].max_size, Some(1024));
+    assert_eq!(budget.tables[0].max_rows, Some(10_000));
+    assert_eq!(budget.tables[1].max_size, Some(1_572_864));
+    assert_eq!(budget.tables[1].max_index_ratio, Some(0.5));
+
+    for bad in [
+        "max_file_size = \"huge\"",
+        "max_file = 10",
+        "[tables.users]\nmax_sise = 10",
+        "[tables.users\n",
+    ] {
+        assert!(
+            matches!(Budget::from_toml(bad), Err(SqduError::InvalidBudget(_))),
+            "{}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn test_redundant_indexes() {
+    let db_path = create_test_db(
+        "redundant",
+        "CREATE TABLE events (id INTEGER PRIMARY KEY, user_id INT, created_at INT,
+                              email TEXT UNIQUE, kind TEXT);
+         CREATE INDEX idx_user ON events(user_id);
+         CREATE INDEX idx_user_created ON events(user_id, created_at);
+         CREATE INDEX idx_kind ON events(kind);
+         CREATE INDEX idx_kind_copy ON events(\"kind\");
+         CREATE INDEX idx_email ON events(email);
+         CREATE INDEX idx_id ON events(id);
+         CREATE INDEX idx_partial ON events(user_id) WHERE kind = 'x';
+         CREATE UNIQUE INDEX idx_user_unique ON events(user_id, created_at, kind);
+         CREATE INDEX idx_kind_desc ON events(kind DESC);
+         CREATE INDEX idx_kind_nocase ON events(kind COLLATE NOCASE);
+         CREATE INDEX idx_kind_lower ON events(lower(kind));
+         CREATE INDEX idx_kind_lower_copy ON events(lower(kind));
+         INSERT INTO events (user_id, created_at, email, kind)
+             VALUES (1, 1, 'a@example.com', 'x'), (2, 2, 'b@example.com', 'y');",
+    );
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    let redundant = analyzer.redundant_indexes("events").unwrap();
+    let found: HashMap<&str, (Redundancy, &str)> = redundant
+        .iter()
+        .map(|r| (r.index.as_str(), (r.reason, r.covered_by.as_str())))
+        .collect();
+
+    // The longest of several covering indexes is named
+    assert_eq!(found["idx_user"], (Redundancy::Prefix, "idx_user_unique"));
+    assert_eq!(
+        found["idx_user_created"],
+        (Redundancy::Prefix, "idx_user_unique")
+    );
+    assert_eq!(found["idx_kind_copy"], (Redundancy::Duplicate, "idx_kind"));
+    assert_eq!(
+        found["idx_email"],
+        (Redundancy::UniqueImplied, "sqlite_autoindex_events_1")
+    );
+    assert_eq!(
+        found["idx_id"],
+        (Redundancy::UniqueImplied, "INTEGER PRIMARY KEY")
+    );
+    assert_eq!(found["idx_partial"], (Redundancy::Duplicate, "idx_user"));
+    // Different order, collation, uniqueness or expressions are not compared
+    assert_eq!(found.len(), 6, "{:?}", redundant);
+    assert!(redundant.iter().all(|r| r.reclaimable_bytes > 0));
+
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+    assert_eq!(report.findings.len(), 6);
+    let text = render(&report, OutputFormat::Text, UnitStyle::Binary);
+    assert!(text.contains("events: idx_user: prefix of idx_user_unique; dropping it reclaims"));
+}
+
+#[test]
+fn test_unindexed_foreign_keys() {
+    let db_path = create_test_db(
+        "foreign-keys",
+        "CREATE TABLE customers (id INTEGER PRIMARY KEY);
+         CREATE TABLE products (sku TEXT PRIMARY KEY);
+         CREATE TABLE orders (
+             id INTEGER PRIMARY KEY REFERENCES customers(id),
+             customer_id INT REFERENCES customers(id),
+             product_sku TEXT REFERENCES products(sku),
+             referrer_id INT REFERENCES customers(id),
+             coupon_owner INT REFERENCES customers(id)
+         );
+         CREATE INDEX idx_orders_product ON orders(Product_Sku, customer_id);
+         CREATE INDEX idx_orders_referrer ON orders(referrer_id) WHERE referrer_id IS NOT NULL;
+         CREATE INDEX idx_orders_late ON orders(product_sku, coupon_owner);",
+    );
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+
+    // Only leading columns of full indexes count, and the rowid is its own index
+    let mut columns: Vec<String> = analyzer
+        .unindexed_foreign_keys("orders")
+        .unwrap()
+        .into_iter()
+        .flat_map(|fk| fk.columns)
+        .collect();
+    columns.sort();
+    assert_eq!(columns, ["coupon_owner", "customer_id", "referrer_id"]);
+    assert!(analyzer
+        .unindexed_foreign_keys("customers")
+        .unwrap()
+        .is_empty());
+
+    let sizes = DatabaseReport::generate_sizes(&mut analyzer).unwrap();
+    assert!(sizes.findings.is_empty());
+    assert_eq!(sizes.tables.len(), 3);
+
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+    let text = render(&report, OutputFormat::Text, UnitStyle::Binary);
+    assert!(text.contains(
+        "orders: customer_id: foreign key to customers.id has no index starting with it"
+    ));
+}
+
+#[test]
+fn test_unindexed_composite_foreign_keys() {
+    let db_path = create_test_db(
+        "composite-foreign-keys",
+        "CREATE TABLE regions (country TEXT, code TEXT, PRIMARY KEY (country, code));
+         CREATE TABLE stores (
+             id INTEGER PRIMARY KEY,
+             country TEXT,
+             region TEXT,
+             billing_country TEXT,
+             billing_region TEXT,
+             FOREIGN KEY (country, region) REFERENCES regions(country, code),
+             FOREIGN KEY (billing_country, billing_region) REFERENCES regions(country, code)
+         );
+         CREATE INDEX idx_stores_region ON stores(region, country, id);
+         CREATE INDEX idx_stores_billing ON stores(billing_country, id, billing_region);",
+    );
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+
+    // Leading columns in another order still serve the key; one finding per key
+    let unindexed = analyzer.unindexed_foreign_keys("stores").unwrap();
+    assert_eq!(unindexed.len(), 1);
+    assert_eq!(unindexed[0].columns, ["billing_country", "billing_region"]);
+    assert_eq!(unindexed[0].to_cols, ["country", "code"]);
+
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+    let text = render(&report, OutputFormat::Text, UnitStyle::Binary);
+    assert!(text.contains(
+        "stores: (billing_country, billing_region): foreign key to regions(country, code) \
+         has no index starting with these columns"
+    ));
+}
+
+#[test]
+fn test_unindexed_implicit_primary_key_foreign_key() {
+    let db_path = create_test_db(
+        "implicit-foreign-key",
+        "CREATE TABLE p (id INTEGER PRIMARY KEY);
+         CREATE TABLE c (id INTEGER PRIMARY KEY, pid INT REFERENCES p);",
+    );
+    let mut analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+
+    // The parent column is left to SQLite, which uses p's primary key
+    let details = analyzer.table_details("c").unwrap();
+    assert_eq!(details.foreign_keys[0].to_col, None);
+    let unindexed = analyzer.unindexed_foreign_keys("c").unwrap();
+    assert_eq!(unindexed.len(), 1);
+    assert_eq!(unindexed[0].columns, ["pid"]);
+    assert!(unindexed[0].to_cols.is_empty());
+
+    let report = DatabaseReport::generate(&mut analyzer).unwrap();
+    let text = render(&report, OutputFormat::Text, UnitStyle::Binary);
+    assert!(text.contains("c: pid: foreign key to p has no index starting with it"));
+}
+
+#[test]
+fn test_workload_parse() {
+    let workload = Workload::parse(
+        "SELECT 1; -- skipped; comment
+         SELECT ';' AS \"a;b\" /* ; */ FROM t;;
+         -- SELECT * FROM commented_out;
+         SELECT [x;y] FROM t",
+    );
+    assert_eq!(
+        workload.statements,
+        [
+            "SELECT 1",
+            "SELECT ';' AS \"a;b\"   FROM t",
+            "SELECT [x;y] FROM t"
+        ]
+    );
+}
+
+#[test]
+fn test_workload_index_usage() {
+    let db_path = create_test_db(
+        "workload",
+        "CREATE TABLE orders (id INTEGER PRIMARY KEY, customer_id INT, status TEXT, sku TEXT UNIQUE,
+                              note TEXT);
+         CREATE INDEX idx_orders_customer ON orders(customer_id);
+         CREATE INDEX \"idx orders status\" ON orders(status);
+         CREATE INDEX idx_orders_customer_status ON orders(customer_id, status);
+         CREATE INDEX idx_unused ON orders(note);
+         INSERT INTO orders (customer_id, status, sku) VALUES (1, 'new', 'a'), (2, 'done', 'b');",
+    );
+    let workload = Workload::parse(
+        "SELECT * FROM orders WHERE customer_id = ?;
+         SELECT * FROM orders WHERE customer_id = ?;
+         SELECT id FROM orders WHERE status = 'new' OR status = :s;
+         SELECT * FROM orders WHERE sku = 'a';
+         DELETE FROM orders WHERE id = 1;
+         SELECT * FROM missing_table;",
+    );
+    let analyzer = Analyzer::open(&db_path, AnalysisOptions::default()).unwrap();
+    let usage = analyzer.index_usage(&workload).unwrap();
+
+    assert_eq!(usage.statements, 6);
+    assert_eq!(usage.failed.len(), 1);
+    assert!(usage.failed[0].error.contains("missing_table"));
+    // Repeated statements count every time
+    let customer_hits =
+        usage.hits("idx_orders_customer") + usage.hits("idx_orders_customer_status");
+    assert_eq!(customer_hits, 2, "{:?}", usage.hits);
+    assert_eq!(usage.hits("sqlite_autoindex_orders_1"), 1);
+    // Named once however often the plan uses it
+    assert_eq!(usage.hits("idx orders status"), 1);
+    assert_eq!(usage.hits("idx_unused"), 0);
+    // Nothing is run, so the deleted row is still there
+    let conn = Connection::open(&db_path).unwrap();
+    let count: u64 = conn
+        .query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(count, 2);
+}